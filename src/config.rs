@@ -0,0 +1,62 @@
+use std::sync::{OnceLock, RwLock};
+
+/// How `Value`'s `Display` writes a value
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum DisplayStyle {
+    /// The whole part with the remaining fraction, `3 (1 / 2)`
+    #[default]
+    Mixed,
+    /// An improper fraction, `7/2`
+    Fraction,
+    /// A decimal rounded to `Config::precision` places, `3.5`
+    Decimal,
+}
+
+/// What the arithmetic operators on `Value` do when a result doesn't fit
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum OverflowMode {
+    #[default]
+    Panic,
+    /// Clamp to `i64::MIN` or `i64::MAX`
+    Saturate,
+}
+
+/// Process wide defaults for embedding the crate, see `set_config`
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Config {
+    pub display: DisplayStyle,
+    /// Decimal places written by `DisplayStyle::Decimal`
+    pub precision: usize,
+    pub overflow: OverflowMode,
+    /// Replace every result of evaluating an expression with a denominator above this with its
+    /// closest approximation that fits, trading exactness for readable fractions. The error
+    /// compounds over a chain of operations, so results are no longer exact once this is set.
+    /// Read once per evaluation by the `Tree` methods without an `EvalMode`, `Value` arithmetic
+    /// stays exact
+    pub max_denominator: Option<i64>,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            display: DisplayStyle::default(),
+            precision: 10,
+            overflow: OverflowMode::default(),
+            max_denominator: None,
+        }
+    }
+}
+
+fn global() -> &'static RwLock<Config> {
+    static CONFIG: OnceLock<RwLock<Config>> = OnceLock::new();
+    CONFIG.get_or_init(|| RwLock::new(Config::default()))
+}
+
+/// The current configuration, `Config::default()` until `set_config` is called
+pub fn config() -> Config {
+    *global().read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Replace the configuration for every thread
+pub fn set_config(config: Config) {
+    *global().write().unwrap_or_else(|e| e.into_inner()) = config;
+}
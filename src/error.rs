@@ -0,0 +1,105 @@
+use std::fmt::{Display, Formatter, Result as fmt_Result};
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum LexError {
+    /// A number literal that doesn't parse, or doesn't fit in a `Value`
+    InvalidNumber(String),
+    /// A character that isn't part of any token, at the given byte offset
+    UnexpectedChar(char, usize),
+    /// Two operands with no operator between them (`2 x`, `(1)(2)`, or `1 2` which would otherwise
+    /// be read as `12`), at the byte offset of the second
+    MissingOperator(usize),
+    /// A unary minus applied directly to another (`--5`), at the byte offset of the second
+    AmbiguousUnary(usize),
+    /// A decimal literal where only integers and fractions are accepted, at the byte offset of the point
+    DecimalNotAllowed(usize),
+    /// An operator function like `add` without exactly two arguments, at the byte offset of its name
+    ExpectedTwoArguments(usize),
+}
+impl Display for LexError {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        match self {
+            Self::InvalidNumber(literal) => write!(f, "Invalid number {:?}", literal),
+            Self::UnexpectedChar(c, pos) => write!(f, "Unexpected character {:?} at {}", c, pos),
+            Self::MissingOperator(pos) => write!(f, "Missing operator before {}", pos),
+            Self::AmbiguousUnary(pos) => write!(f, "Repeated unary minus at {}", pos),
+            Self::DecimalNotAllowed(pos) => write!(f, "Decimal point at {}, use a fraction", pos),
+            Self::ExpectedTwoArguments(pos) => {
+                write!(f, "Expected two arguments for the call at {}", pos)
+            }
+        }
+    }
+}
+impl std::error::Error for LexError {}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum EvalError {
+    /// The operation is only defined for integer values
+    NotAnInteger,
+    /// A step of an integer-only evaluation doesn't come out whole
+    NonIntegerResult,
+    /// The value is outside of the domain of the operation
+    OutOfDomain,
+    /// A `$n` history reference with no matching result
+    HistoryOutOfRange(usize),
+    /// A variable with no value bound to it
+    UnknownVariable(String),
+    /// A function call with no function registered under its name
+    UnknownFunction(String),
+    /// A `where` clause binding that isn't of the form `name = value`
+    InvalidBinding(String),
+    /// The input couldn't be tokenized
+    Lex(LexError),
+    /// Nothing to evaluate
+    EmptyExpression,
+    /// A `(` without a matching `)`
+    UnbalancedParens,
+    /// An operator without enough operands, `1 +`
+    MissingOperand,
+    /// Operands without an operator between them, `2 (3)`
+    MissingOperator,
+    /// A `?` without a matching `:`, or a `:` outside of a conditional
+    InvalidConditional,
+    DivideByZero,
+    /// An intermediate result doesn't fit in an `i64` ratio
+    Overflow,
+    /// The expression is larger than allowed
+    LimitExceeded,
+    /// Evaluation ran past its time budget
+    Timeout,
+    /// The input couldn't be read, with the message of the underlying `std::io::Error`
+    Io(String),
+    /// A tree couldn't be read from JSON, with what was wrong
+    InvalidJson(String),
+}
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        match self {
+            Self::NotAnInteger => write!(f, "Expected an integer value"),
+            Self::NonIntegerResult => write!(f, "Result is not an integer"),
+            Self::OutOfDomain => write!(f, "Value is out of the domain of the operation"),
+            Self::HistoryOutOfRange(n) => write!(f, "No result ${} in the history", n),
+            Self::UnknownVariable(name) => write!(f, "Unknown variable {:?}", name),
+            Self::UnknownFunction(name) => write!(f, "Unknown function {:?}", name),
+            Self::InvalidBinding(binding) => write!(f, "Invalid binding {:?}", binding),
+            Self::Lex(e) => write!(f, "{}", e),
+            Self::EmptyExpression => write!(f, "Empty expression"),
+            Self::UnbalancedParens => write!(f, "Unbalanced parentheses"),
+            Self::MissingOperand => write!(f, "Operator is missing an operand"),
+            Self::MissingOperator => write!(f, "Operands are missing an operator"),
+            Self::InvalidConditional => write!(f, "Conditional is missing its `?` or `:`"),
+            Self::DivideByZero => write!(f, "Divide by zero"),
+            Self::Overflow => write!(f, "Overflow"),
+            Self::LimitExceeded => write!(f, "Expression exceeds the allowed size"),
+            Self::Timeout => write!(f, "Evaluation took too long"),
+            Self::Io(message) => write!(f, "Failed to read input: {}", message),
+            Self::InvalidJson(reason) => write!(f, "Invalid expression JSON: {}", reason),
+        }
+    }
+}
+impl std::error::Error for EvalError {}
+impl From<LexError> for EvalError {
+    fn from(e: LexError) -> Self {
+        Self::Lex(e)
+    }
+}
@@ -1,18 +1,26 @@
-use crate::Value;
+use crate::{tree::EvalMode, EvalError, Fraction, LexError, Value};
 
 use std::{
-    // string::{ ToString },
+    convert::TryFrom,
     fmt::{Debug, Display, Formatter, Result as fmt_Result},
+    ops::Range,
     str::FromStr,
 };
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 pub enum Operator {
     Add,
     Sub,
     Mul,
     Div,
+    FloorDiv,
+    Mod,
+    Pow,
     USub,
+    /// The `?` of `cond ? a : b`, takes the condition and the `Else` holding both branches
+    Cond,
+    /// The `:` of `cond ? a : b`, only valid as the right operand of a `Cond`
+    Else,
 }
 impl Operator {
     pub fn from_char(c: char) -> Option<Self> {
@@ -21,22 +29,105 @@ impl Operator {
             '-' => Some(Self::Sub),
             '*' => Some(Self::Mul),
             '/' => Some(Self::Div),
+            '%' => Some(Self::Mod),
+            '^' => Some(Self::Pow),
             'u' => Some(Self::USub),
+            '?' => Some(Self::Cond),
+            ':' => Some(Self::Else),
             _ => None,
         }
     }
 
+    /// Operators written with two characters, `**` is an alias of `^`
+    pub fn from_chars(first: char, second: char) -> Option<Self> {
+        match (first, second) {
+            ('/', '/') => Some(Self::FloorDiv),
+            ('*', '*') => Some(Self::Pow),
+            _ => None,
+        }
+    }
+
+    /// The first character of the operator, see `to_str` for the full symbol of multi-character operators
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_char(&self) -> char {
         match self {
             Self::Add => '+',
             Self::Sub => '-',
             Self::Mul => '*',
-            Self::Div => '/',
+            Self::Div | Self::FloorDiv => '/',
+            Self::Mod => '%',
+            Self::Pow => '^',
             Self::USub => 'u',
+            Self::Cond => '?',
+            Self::Else => ':',
+        }
+    }
+
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::FloorDiv => "//",
+            Self::Mod => "%",
+            Self::Pow => "^",
+            Self::USub => "u",
+            Self::Cond => "?",
+            Self::Else => ":",
         }
     }
 
     pub fn evaluate(&self, left: Value, right: Value) -> Value {
+        self.evaluate_with_mode(left, right, EvalMode::default())
+    }
+
+    /// Apply the operator without panicking, division by zero and overflow are reported as errors
+    pub fn try_evaluate(&self, left: Value, right: Value) -> Result<Value, EvalError> {
+        self.try_evaluate_with_mode(left, right, EvalMode::default())
+    }
+
+    /// `try_evaluate` under the conventions of `mode`
+    pub fn try_evaluate_with_mode(
+        &self,
+        left: Value,
+        right: Value,
+        mode: EvalMode,
+    ) -> Result<Value, EvalError> {
+        let divides_by_zero = match self {
+            Self::Div | Self::FloorDiv | Self::Mod => right == 0,
+            Self::Pow => left == 0 && matches!(right, Value::Integer(exponent) if exponent < 0),
+            _ => false,
+        };
+        if divides_by_zero {
+            return match self {
+                _ if !mode.non_finite => Err(EvalError::DivideByZero),
+                Self::Mod => Ok(Value::Undefined),
+                Self::Pow => Ok(Value::Infinity),
+                _ => Ok(left.divide_by_zero()),
+            };
+        }
+
+        let overflow = |v: Option<Value>| v.ok_or(EvalError::Overflow);
+        let result = match self {
+            Self::Add => left.try_add(right),
+            Self::Sub => left.try_sub(right),
+            Self::Mul => left.try_mul(right),
+            Self::Div => left.try_div(right),
+            Self::FloorDiv => overflow(left.checked_div_floor(right)),
+            Self::Mod => overflow(left.checked_rem(right, mode.modulo)),
+            Self::Pow => match right {
+                Value::Integer(exponent) => overflow(left.checked_powi(exponent)),
+                _ => Err(EvalError::NotAnInteger),
+            },
+            Self::USub => overflow(right.checked_neg()),
+            // the branches are picked by the tree, there's nothing to apply to two values
+            Self::Cond | Self::Else => Err(EvalError::InvalidConditional),
+        };
+        result.map(|v| v.capped(mode.max_denominator))
+    }
+
+    pub fn evaluate_with_mode(&self, left: Value, right: Value, mode: EvalMode) -> Value {
         match self {
             Self::Add => left + right,
             Self::Sub => left - right,
@@ -48,7 +139,14 @@ impl Operator {
                     left / right
                 }
             }
+            Self::FloorDiv => left.div_floor(right),
+            Self::Mod => left.rem(right, mode.modulo),
+            Self::Pow => match right {
+                Value::Integer(exponent) => left.pow(exponent),
+                _ => panic!("Non-integer exponents are not supported"),
+            },
             Self::USub => -right,
+            Self::Cond | Self::Else => panic!("{}", EvalError::InvalidConditional),
         }
     }
 }
@@ -56,23 +154,27 @@ impl FromStr for Operator {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Operator, Self::Err> {
-        match Operator::from_char(
-            s.chars()
-                .next()
-                .expect("Cannot parse empty string into operator"),
-        ) {
+        let mut chars = s.chars();
+        let first = chars
+            .next()
+            .expect("Cannot parse empty string into operator");
+        let multi = chars
+            .next()
+            .and_then(|second| Operator::from_chars(first, second));
+        match multi.or_else(|| Operator::from_char(first)) {
             Some(n) => Ok(n),
             None => Err("Unknown operator"),
         }
     }
 }
-impl ToString for Operator {
-    fn to_string(&self) -> String {
-        format!("{}", self.to_char())
+impl Display for Operator {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        // `to_str` rather than `to_char`, `//` is two characters
+        write!(f, "{}", self.to_str())
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug, Hash)]
 pub enum Paren {
     Left,
     Right,
@@ -86,6 +188,7 @@ impl Paren {
         }
     }
 
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_char(&self) -> char {
         match self {
             Self::Left => '(',
@@ -107,37 +210,56 @@ impl FromStr for Paren {
         }
     }
 }
-impl ToString for Paren {
-    fn to_string(&self) -> String {
-        format!("{}", self.to_char())
+impl Display for Paren {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        write!(f, "{}", self.to_char())
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Hash)]
 pub enum Token {
     Operator(Operator),
     Value(Value),
     Paren(Paren),
+    Variable(String),
+    /// An identifier directly followed by `(`, called on the parenthesized expression
+    Function(String),
 }
 impl Token {
     #[cfg(test)]
     pub fn new(literal: &str) -> Self {
-        match literal.parse::<Token>() {
-            Ok(t) => t,
-            Err(e) => panic!(e),
+        Self::from_literal(literal).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// The token `literal` is read as on its own (`"3"`, `"//"`, `"u"` for unary minus, `"x"`), for
+    /// building expected token streams without panicking
+    pub fn from_literal(literal: &str) -> Result<Self, LexError> {
+        // an empty literal isn't any token, and parsing one as an `Operator` panics
+        if literal.is_empty() {
+            return Err(LexError::InvalidNumber(String::new()));
         }
+        literal
+            .parse()
+            .map_err(|_| LexError::InvalidNumber(literal.to_string()))
     }
 }
 impl FromStr for Token {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Token, Self::Err> {
-        if let Ok(v) = s.parse::<f64>() {
+        let is_identifier = !s.is_empty() && s.chars().all(|c| c.is_ascii_alphabetic());
+
+        // single letters may be operators (`u`), `inf` and `nan` would parse as floats
+        if is_identifier && s.len() > 1 {
+            Ok(Token::Variable(s.to_string()))
+        } else if let Ok(v) = s.parse::<f64>() {
             Ok(Token::Value(v.into()))
         } else if let Ok(op) = s.parse::<Operator>() {
             Ok(Token::Operator(op))
         } else if let Ok(p) = s.parse::<Paren>() {
             Ok(Token::Paren(p))
+        } else if is_identifier {
+            Ok(Token::Variable(s.to_string()))
         } else {
             Err("Unexpected literal")
         }
@@ -150,13 +272,14 @@ impl Display for Token {
                 Token::Operator(op) => op.to_string(),
                 Token::Paren(p) => p.to_string(),
                 Token::Value(v) => v.to_string(),
+                Token::Variable(name) | Token::Function(name) => name.clone(),
             }
         })
     }
 }
-impl Into<Value> for Token {
-    fn into(self) -> Value {
-        match self {
+impl From<Token> for Value {
+    fn from(token: Token) -> Self {
+        match token {
             Token::Value(v) => v,
             _ => panic!("Attempt to coerce non-value Token to Value"),
         }
@@ -164,103 +287,841 @@ impl Into<Value> for Token {
 }
 
 /// Parse the string `s` into a Token stream
+///
+/// A mixed number is written `whole_numerator/denominator` with no spaces, so `3_1/2` is the single
+/// value `7/2` while `3 1/2` is still lexed as separate tokens
 /// ```rust
+/// # use rational_calculator::lex::{tokenize, Operator, Paren, Token};
 /// let tokens = vec![
-///     Token::new("("),
-///     Token::new("10"),
-///     Token::new("+"),
-///     Token::new("5"),
-///     Token::new(")"),
+///     Token::Paren(Paren::Left),
+///     Token::Value(10.0.into()),
+///     Token::Operator(Operator::Add),
+///     Token::Value(5.0.into()),
+///     Token::Paren(Paren::Right),
 /// ];
 /// assert!(tokens == tokenize("(10+5)"));
 /// ```
 pub fn tokenize(s: &str) -> Vec<Token> {
-    // /*DEBUG:*/ eprintln!("Begin tokenization");
-    let mut buffer = String::new();
+    try_tokenize(s).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Parse the string `s` into a Token stream, reporting invalid literals rather than panicking
+pub fn try_tokenize(s: &str) -> Result<Vec<Token>, LexError> {
     let mut tokens: Vec<Token> = Vec::new();
+    lex(s, 0, Mode::Lenient, &mut tokens, &mut Vec::new())?;
+    Ok(tokens)
+}
 
-    let cleaned = s
-        .chars()
-        .filter(|&c| "1234567890./*-+^()".contains(c))
-        .collect::<String>();
+/// Parse the string `s` into a Token stream, rejecting anything `tokenize` would silently normalize:
+/// unknown characters, operands with no operator between them, and repeated unary minus
+pub fn tokenize_strict(s: &str) -> Result<Vec<Token>, LexError> {
+    let mut tokens: Vec<Token> = Vec::new();
+    lex(s, 0, Mode::Strict, &mut tokens, &mut Vec::new())?;
+    Ok(tokens)
+}
+
+/// `tokenize_with_locale` for implicit multiplication, where `1.5(3)` is `1.5 (3)` rather than a
+/// repeating decimal
+pub(crate) fn tokenize_implicit_with_locale(
+    s: &str,
+    locale: LocaleOptions,
+) -> Result<Vec<Token>, LexError> {
+    let mut tokens: Vec<Token> = Vec::new();
+    lex_with(
+        s,
+        0,
+        Mode::Lenient,
+        LexOptions {
+            locale,
+            implicit_mul: true,
+            ..LexOptions::default()
+        },
+        &mut tokens,
+        &mut Vec::new(),
+        &mut Scratch::default(),
+    )?;
+    Ok(tokens)
+}
+
+/// Parse the string `s` into a Token stream, erroring on operands with no operator between them
+/// (`2 3`, `(1)(2)`) rather than joining the digits or leaving them for implicit multiplication
+pub fn tokenize_explicit(s: &str) -> Result<Vec<Token>, LexError> {
+    let mut tokens: Vec<Token> = Vec::new();
+    lex(s, 0, Mode::Explicit, &mut tokens, &mut Vec::new())?;
+    Ok(tokens)
+}
+
+/// Parse the string `s` into a Token stream, skipping unknown characters and invalid literals and
+/// returning an error for each one alongside the tokens that could be read
+pub fn tokenize_recover(s: &str) -> (Vec<Token>, Vec<LexError>) {
+    let (mut tokens, mut errors) = (Vec::new(), Vec::new());
+    lex(
+        s,
+        0,
+        Mode::Recover(&mut errors),
+        &mut tokens,
+        &mut Vec::new(),
+    )
+    .expect("Something went wrong! (recovering lexer returned an error)");
+    (tokens, errors)
+}
+
+/// Parse the string `s` into a Token stream, with numbers written the way `locale` writes them
+pub fn tokenize_with_locale(s: &str, locale: LocaleOptions) -> Result<Vec<Token>, LexError> {
+    let mut tokens: Vec<Token> = Vec::new();
+    lex_with(
+        s,
+        0,
+        Mode::Lenient,
+        LexOptions {
+            locale,
+            ..LexOptions::default()
+        },
+        &mut tokens,
+        &mut Vec::new(),
+        &mut Scratch::default(),
+    )?;
+    Ok(tokens)
+}
+
+/// `tokenize_explicit` in `locale`
+pub(crate) fn tokenize_explicit_with_locale(
+    s: &str,
+    locale: LocaleOptions,
+) -> Result<Vec<Token>, LexError> {
+    let mut tokens: Vec<Token> = Vec::new();
+    lex_with(
+        s,
+        0,
+        Mode::Explicit,
+        LexOptions {
+            locale,
+            ..LexOptions::default()
+        },
+        &mut tokens,
+        &mut Vec::new(),
+        &mut Scratch::default(),
+    )?;
+    Ok(tokens)
+}
+
+/// How numbers are written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LocaleOptions {
+    /// Read `,` as the decimal point and `.` as digit grouping that's ignored, so `1.000,5` is
+    /// `1000.5`. Otherwise `.` is the point and `,` is dropped like other unknown characters, so
+    /// `1,000.5` is `1000.5` too. The arguments of `OPERATOR_FUNCTIONS` are separated by `;`
+    /// rather than `,` with a decimal comma, `add(1,5; 2)`
+    pub decimal_comma: bool,
+}
+impl LocaleOptions {
+    pub fn decimal_point(self) -> char {
+        if self.decimal_comma {
+            ','
+        } else {
+            '.'
+        }
+    }
+}
+
+/// Lexer buffers that can be kept between expressions, so tokenizing many of them in a loop doesn't
+/// allocate for each one
+#[derive(Debug, Default)]
+pub struct Tokenizer {
+    scratch: Scratch,
+    starts: Vec<usize>,
+    locale: LocaleOptions,
+}
+impl Tokenizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_locale(locale: LocaleOptions) -> Self {
+        Tokenizer {
+            locale,
+            ..Self::default()
+        }
+    }
+
+    /// Replace the contents of `out` with the Token stream of `s`, lexed like `try_tokenize`
+    pub fn tokenize_into(&mut self, s: &str, out: &mut Vec<Token>) -> Result<(), LexError> {
+        out.clear();
+        self.starts.clear();
+        lex_with(
+            s,
+            0,
+            Mode::Lenient,
+            LexOptions {
+                locale: self.locale,
+                ..LexOptions::default()
+            },
+            out,
+            &mut self.starts,
+            &mut self.scratch,
+        )
+    }
+}
+
+/// Parse the string `s` into a Token stream, replacing each `$n` with the `n`th (1-based) value in `history`
+pub fn tokenize_with_history(s: &str, history: &[Value]) -> Result<Vec<Token>, EvalError> {
+    // look every reference up first, the lexer reads them back in order as it meets them
+    let mut references = Vec::new();
+    for (dollar, _) in s.match_indices('$') {
+        let digits = s[dollar + 1..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(s.len() - dollar - 1);
+        if digits > 0 {
+            let n = s[dollar + 1..dollar + 1 + digits]
+                .parse::<usize>()
+                .map_err(|_| EvalError::HistoryOutOfRange(usize::MAX))?;
+            let value = n
+                .checked_sub(1)
+                .and_then(|idx| history.get(idx))
+                .ok_or(EvalError::HistoryOutOfRange(n))?;
+            references.push(*value);
+        }
+    }
+
+    let mut tokens: Vec<Token> = Vec::new();
+    lex_with(
+        s,
+        0,
+        Mode::Lenient,
+        LexOptions {
+            references: Some(&references),
+            ..LexOptions::default()
+        },
+        &mut tokens,
+        &mut Vec::new(),
+        &mut Scratch::default(),
+    )?;
+    Ok(tokens)
+}
+
+/// How `lex` treats input it can't make sense of
+enum Mode<'a> {
+    /// Drop unknown characters, error on invalid literals
+    Lenient,
+    /// Error on anything `Lenient` would drop or reinterpret
+    Strict,
+    /// `Lenient`, but error on operands with no operator between them
+    Explicit,
+    /// Record unknown characters and invalid literals to the list and carry on without them
+    Recover(&'a mut Vec<LexError>),
+}
+
+/// Return `error` from `lex`, unless it's recovering
+fn report(mode: &mut Mode, error: LexError) -> Result<(), LexError> {
+    match mode {
+        Mode::Recover(errors) => {
+            errors.push(error);
+            Ok(())
+        }
+        _ => Err(error),
+    }
+}
+
+/// Functions written in place of an operator, `add(2, 3)` is `(2) + (3)`. They're read before any
+/// registered function of the same name
+pub const OPERATOR_FUNCTIONS: &[(&str, Operator)] = &[
+    ("add", Operator::Add),
+    ("sub", Operator::Sub),
+    ("mul", Operator::Mul),
+    ("div", Operator::Div),
+    ("pow", Operator::Pow),
+];
 
-    let mut idx = 0;
+/// An operator function whose `)` hasn't been reached yet
+struct OperatorCall {
+    op: Operator,
+    /// How many parens are open inside of the call's own `(`
+    depth: usize,
+    /// Where the name starts
+    start: usize,
+    /// Whether the argument separator has been read
+    separated: bool,
+}
 
-    while let Some(c) = cleaned.chars().nth(idx) {
-        // /*DEBUG:*/ eprint!("C: {}, IDX: {} -> ", c, idx);
+/// The working storage of `lex`, cleared at the start of each call
+#[derive(Debug, Default)]
+struct Scratch {
+    buffer: String,
+    cleaned: Vec<(usize, char)>,
+}
+
+/// Lex `s[offset..]` onto the end of `tokens`, pushing the byte offset each new token starts at to `starts`
+///
+/// Tokens already in `tokens` are used as context for unary operators
+fn lex(
+    s: &str,
+    offset: usize,
+    mode: Mode,
+    tokens: &mut Vec<Token>,
+    starts: &mut Vec<usize>,
+) -> Result<(), LexError> {
+    lex_with(
+        s,
+        offset,
+        mode,
+        LexOptions::default(),
+        tokens,
+        starts,
+        &mut Scratch::default(),
+    )
+}
+
+/// What `lex_with` reads on top of the plain syntax
+#[derive(Clone, Copy, Debug, Default)]
+struct LexOptions<'a> {
+    locale: LocaleOptions,
+    /// Read each `$n` as the next of these
+    references: Option<&'a [Value]>,
+    /// Leave digits in parens after a decimal to implicit multiplication, so `0.5(2)` is `0.5 (2)`
+    /// rather than the repeating `0.5222...`
+    implicit_mul: bool,
+}
+
+/// `lex` with the extras of `options`, working in the buffers of `scratch`
+fn lex_with(
+    s: &str,
+    offset: usize,
+    mut mode: Mode,
+    options: LexOptions,
+    tokens: &mut Vec<Token>,
+    starts: &mut Vec<usize>,
+    scratch: &mut Scratch,
+) -> Result<(), LexError> {
+    // /*DEBUG:*/ eprintln!("Begin tokenization");
+    let LexOptions {
+        locale,
+        references,
+        implicit_mul,
+    } = options;
+    let Scratch { buffer, cleaned } = scratch;
+    buffer.clear();
+    cleaned.clear();
+    let mut buffer_start = offset;
+    let mut buffer_end = offset;
+
+    let strict = matches!(mode, Mode::Strict);
+    let explicit = strict || matches!(mode, Mode::Explicit);
+    let recovering = matches!(mode, Mode::Recover(_));
+    // the comma is taken by decimals in some locales
+    let separator = if locale.decimal_comma { ';' } else { ',' };
+    let is_allowed = |c: char| {
+        c.is_ascii_alphabetic()
+            || "1234567890._/*-+%^()?:|−".contains(c)
+            || c == separator
+            || (locale.decimal_comma && c == ',')
+            || (references.is_some() && c == '$')
+            || superscript_digit(c).is_some()
+    };
+    if strict {
+        if let Some((i, c)) = s[offset..]
+            .char_indices()
+            .find(|&(_, c)| !is_allowed(c) && !c.is_whitespace())
+        {
+            return Err(LexError::UnexpectedChar(c, offset + i));
+        }
+    }
+    cleaned.extend(
+        s[offset..]
+            .char_indices()
+            .filter_map(|(i, c)| {
+                let c = match c {
+                    // pasted math often has the Unicode minus sign
+                    '−' => '-',
+                    // with a decimal comma the point only groups digits
+                    '.' if locale.decimal_comma => return None,
+                    ',' if locale.decimal_comma => '.',
+                    c => c,
+                };
+                Some((offset + i, c))
+            })
+            // unknown characters are kept when recovering, so they split operands apart and get reported
+            .filter(|&(_, c)| is_allowed(c) || (recovering && !c.is_whitespace())),
+    );
+    let ends_operand = |token: Option<&Token>| {
+        matches!(
+            token,
+            Some(Token::Value(_)) | Some(Token::Variable(_)) | Some(Token::Paren(Paren::Right))
+        )
+    };
+
+    // `|` that are still waiting for their closing bar
+    let mut open_bars = 0;
+    // unclosed `(` and the operator functions among them
+    let mut depth = 0;
+    let mut calls: Vec<OperatorCall> = Vec::new();
+    let mut references = references.unwrap_or_default().iter();
+    let mut chars = cleaned.iter().copied().peekable();
+
+    while let Some((pos, c)) = chars.next() {
+        // /*DEBUG:*/ eprint!("C: {}, POS: {} -> ", c, pos);
+
+        // the separator only means something directly inside an operator function, anywhere else it's
+        // dropped without splitting the number around it, so `1,000` is still `1000`
+        if c == separator && calls.last().is_none_or(|call| call.depth != depth) {
+            if strict || recovering {
+                report(&mut mode, LexError::UnexpectedChar(c, pos))?;
+            }
+            continue;
+        }
+
+        if !buffer.is_empty() {
+            // c continues the number (0-9 or .) or identifier (a-z) in the buffer, push it to the buffer
+            // the separators of a mixed number (3_1/2) only count when nothing was stripped between them
+            if is_continuation(buffer, c) && (buffer_end == pos || !"_/".contains(c)) {
+                // /*DEBUG:*/ eprintln!("Number or identifier: {}", c);
+                if explicit && buffer_end != pos {
+                    return Err(LexError::MissingOperator(pos));
+                }
+                buffer.push(c);
+                buffer_end = pos + c.len_utf8();
+                continue;
+            }
+
+            // digits in parens directly after a decimal repeat forever, `0.1(6)` is `0.1666...`
+            if c == '('
+                && !implicit_mul
+                && buffer_end == pos
+                && buffer.contains('.')
+                && !buffer.contains('_')
+            {
+                let mut ahead = chars.clone();
+                let mut repeating = String::new();
+                let mut end = pos + c.len_utf8();
+                while let Some((_, digit)) =
+                    ahead.next_if(|&(next_pos, next)| next_pos == end && next.is_ascii_digit())
+                {
+                    repeating.push(digit);
+                    end += digit.len_utf8();
+                }
+                let closed = ahead
+                    .next_if(|&(next_pos, next)| next_pos == end && next == ')')
+                    .is_some();
+                if closed && !repeating.is_empty() {
+                    match parse_repeating(buffer, &repeating) {
+                        Some(v) => {
+                            tokens.push(Token::Value(v));
+                            starts.push(buffer_start);
+                        }
+                        None => report(
+                            &mut mode,
+                            LexError::InvalidNumber(format!("{}({})", buffer, repeating)),
+                        )?,
+                    }
+                    buffer.clear();
+                    chars = ahead;
+                    continue;
+                }
+            }
+
+            // otherwise the buffer is complete, push it to output and carry on with c
+            // /*DEBUG:*/ eprintln!("Commit buffer: {}", buffer);
+            match commit(buffer) {
+                // `name(` is a call rather than a variable
+                Ok(Token::Variable(name)) if c == '(' && buffer_end == pos => {
+                    match OPERATOR_FUNCTIONS
+                        .iter()
+                        .find(|&&(function, _)| function == name)
+                    {
+                        // the arguments are grouped inside of another pair of parens
+                        Some(&(_, op)) => {
+                            tokens.push(Token::Paren(Paren::Left));
+                            calls.push(OperatorCall {
+                                op,
+                                depth: depth + 1,
+                                start: buffer_start,
+                                separated: false,
+                            });
+                        }
+                        None => tokens.push(Token::Function(name)),
+                    }
+                    starts.push(buffer_start);
+                }
+                Ok(token) => {
+                    tokens.push(token);
+                    starts.push(buffer_start);
+                }
+                Err(e) => report(&mut mode, e)?,
+            }
+            buffer.clear();
+        }
 
         // check for unary operators (will always be first or directly following another operator (thanks greg!))
         // unwrap or will make this evalute true if it's the first item in the expression
         match tokens.last().unwrap_or(&Token::Operator(Operator::Add)) {
-            Token::Operator(_) | Token::Paren(Paren::Left) => {
-                if buffer.is_empty() && c == '-' && buffer.is_empty() {
-                    // /*DEBUG:*/ eprintln!("Unary minus");
-                    tokens.push(Token::Operator(Operator::USub));
-                    idx += 1;
-                    continue;
+            Token::Operator(_) | Token::Paren(Paren::Left) if c == '-' => {
+                // /*DEBUG:*/ eprintln!("Unary minus");
+                if strict && tokens.last() == Some(&Token::Operator(Operator::USub)) {
+                    return Err(LexError::AmbiguousUnary(pos));
                 }
+                tokens.push(Token::Operator(Operator::USub));
+                starts.push(pos);
+                continue;
             }
+            // unary plus doesn't change anything, there's no token for it
+            Token::Operator(_) | Token::Paren(Paren::Left) if c == '+' => continue,
             _ => (),
         }
 
-        // c is a number (0-9 or .), push it to the buffer
-        if c.is_numeric() || c == '.' {
-            // /*DEBUG:*/ eprintln!("Number: {}", c);
+        // c starts a number or identifier
+        if is_continuation(buffer, c) {
+            // /*DEBUG:*/ eprintln!("Number or identifier: {}", c);
+            if explicit && ends_operand(tokens.last()) {
+                return Err(LexError::MissingOperator(pos));
+            }
+            buffer_start = pos;
             buffer.push(c);
+            buffer_end = pos + c.len_utf8();
         }
-        // if c is not a number, but there is something in the buffer, push the buffer to output
-        else if !buffer.is_empty() {
-            // /*DEBUG:*/ eprintln!("Commit number: {}", buffer);
-            tokens.push(
-                buffer
-                    .parse()
-                    .expect(&format!("Failed to parse buffer: {:?}", buffer)),
-            );
-            buffer = String::new();
-            idx -= 1;
+        // Operators spanning two adjacent characters
+        else if let Some(op) = chars
+            .peek()
+            .filter(|&&(next_pos, _)| next_pos == pos + c.len_utf8())
+            .and_then(|&(_, next)| Operator::from_chars(c, next))
+        {
+            // /*DEBUG:*/ eprintln!("Operator: {:?}", op);
+            chars.next();
+            tokens.push(Token::Operator(op));
+            starts.push(pos);
+        }
+        // A run of superscript digits is a power of whatever came before it, `5²` is `5^2`
+        else if let Some(mut exponent) = superscript_digit(c) {
+            let mut end = pos + c.len_utf8();
+            while let Some((next_end, digit)) = chars
+                .peek()
+                .filter(|&&(next_pos, _)| next_pos == end)
+                .and_then(|&(next_pos, next)| {
+                    Some((next_pos + next.len_utf8(), superscript_digit(next)?))
+                })
+            {
+                chars.next();
+                end = next_end;
+                // past `i64::MAX` only 0 and ±1 have a power that fits, so the exponent saturates
+                // keeping the parity of its last digit for raising -1 to it
+                exponent = exponent
+                    .checked_mul(10)
+                    .and_then(|exponent| exponent.checked_add(digit))
+                    .unwrap_or(i64::MAX - 1 + digit % 2);
+            }
+            tokens.push(Token::Operator(Operator::Pow));
+            starts.push(pos);
+            tokens.push(Token::Value(Value::Integer(exponent)));
+            starts.push(pos);
+        }
+        // `$n` is a value from the history, it was looked up before lexing
+        else if c == '$' {
+            let mut end = pos + c.len_utf8();
+            let mut digits = 0;
+            while chars
+                .next_if(|&(next_pos, next)| next_pos == end && next.is_ascii_digit())
+                .is_some()
+            {
+                end += 1;
+                digits += 1;
+            }
+            if digits > 0 {
+                let value = references
+                    .next()
+                    .expect("Something went wrong! (more `$n` than references)");
+                tokens.push(Token::Value(*value));
+                starts.push(pos);
+            }
+        }
+        // `|x|` is `abs(x)`, a bar following an operand closes the innermost open one so the bars
+        // of `|a| * |b|` pair up left to right
+        else if c == '|' {
+            if open_bars > 0 && ends_operand(tokens.last()) {
+                open_bars -= 1;
+                tokens.push(Token::Paren(Paren::Right));
+                starts.push(pos);
+            } else {
+                if explicit && ends_operand(tokens.last()) {
+                    return Err(LexError::MissingOperator(pos));
+                }
+                open_bars += 1;
+                tokens.push(Token::Function("abs".to_string()));
+                starts.push(pos);
+                tokens.push(Token::Paren(Paren::Left));
+                starts.push(pos);
+            }
+        }
+        // `, ` of an operator function is `) op (`
+        else if c == separator {
+            let call = calls
+                .last_mut()
+                .expect("Something went wrong! (argument separator outside of a call)");
+            if call.separated {
+                report(&mut mode, LexError::ExpectedTwoArguments(call.start))?;
+            } else {
+                call.separated = true;
+                tokens.push(Token::Paren(Paren::Right));
+                tokens.push(Token::Operator(call.op));
+                tokens.push(Token::Paren(Paren::Left));
+                starts.extend([pos; 3].iter());
+            }
         }
         // Handle operators and parens normally
         else if let Some(op) = Operator::from_char(c) {
             // /*DEBUG:*/ eprintln!("Operator: {:?}", op);
             tokens.push(Token::Operator(op));
+            starts.push(pos);
         } else if let Some(p) = Paren::from_char(c) {
             // /*DEBUG:*/ eprintln!("Paren: {:?}", p);
+            if explicit && p == Paren::Left && ends_operand(tokens.last()) {
+                return Err(LexError::MissingOperator(pos));
+            }
             tokens.push(Token::Paren(p));
-        }
+            starts.push(pos);
 
-        idx += 1;
+            match p {
+                Paren::Left => depth += 1,
+                Paren::Right => {
+                    if let Some(call) = calls.pop_if(|call| call.depth == depth) {
+                        if !call.separated {
+                            report(&mut mode, LexError::ExpectedTwoArguments(call.start))?;
+                        }
+                        tokens.push(Token::Paren(Paren::Right));
+                        starts.push(pos);
+                    }
+                    depth = depth.saturating_sub(1);
+                }
+            }
+        } else if strict || recovering {
+            report(&mut mode, LexError::UnexpectedChar(c, pos))?;
+        }
     }
 
     if !buffer.is_empty() {
-        tokens.push(buffer.parse().expect("Failed to parse token from buffer"));
+        match commit(buffer) {
+            Ok(token) => {
+                tokens.push(token);
+                starts.push(buffer_start);
+            }
+            Err(e) => report(&mut mode, e)?,
+        }
     }
     // /*DEBUG*/ eprintln!("End tokenization\n");
 
-    tokens
+    Ok(())
+}
+
+/// The value of a Unicode superscript digit, `²` -> 2
+fn superscript_digit(c: char) -> Option<i64> {
+    "⁰¹²³⁴⁵⁶⁷⁸⁹"
+        .chars()
+        .position(|digit| digit == c)
+        .map(|position| position as i64)
+}
+
+/// Whether `c` belongs to the same number or identifier as what's in `buffer`
+fn is_continuation(buffer: &str, c: char) -> bool {
+    // superscripts are numeric too, but they're exponents rather than digits
+    let is_number = |c: char| (c.is_numeric() && superscript_digit(c).is_none()) || c == '.';
+    match buffer.chars().next() {
+        None => is_number(c) || c.is_ascii_alphabetic(),
+        Some(first) if first.is_ascii_alphabetic() => c.is_ascii_alphabetic(),
+        // a whole number followed by `_` starts a mixed number, which then takes a single `/`
+        Some(_) if c == '_' => buffer.chars().all(|c| c.is_ascii_digit()),
+        Some(_) if c == '/' => buffer.contains('_') && !buffer.contains('/'),
+        Some(_) if c == '.' => !buffer.contains('_'),
+        Some(_) => is_number(c),
+    }
+}
+
+/// Turn a complete number or identifier buffer into its token
+fn commit(buffer: &str) -> Result<Token, LexError> {
+    if buffer.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        Ok(Token::Variable(buffer.to_string()))
+    } else if buffer.contains('_') {
+        parse_mixed_number(buffer)
+            .map(Token::Value)
+            .ok_or_else(|| LexError::InvalidNumber(buffer.to_string()))
+    } else {
+        parse_number(buffer)
+            .map(Token::Value)
+            .ok_or_else(|| LexError::InvalidNumber(buffer.to_string()))
+    }
+}
+
+/// Parse a mixed number `whole_numerator/denominator`
+fn parse_mixed_number(buffer: &str) -> Option<Value> {
+    let (whole, fraction) = buffer.split_at(buffer.find('_')?);
+    let (numerator, denominator) = fraction[1..].split_at(fraction[1..].find('/')?);
+
+    let whole = Value::from(whole.parse::<i64>().ok()?);
+    let numerator = Value::from(numerator.parse::<i64>().ok()?);
+    let denominator = Value::from(denominator[1..].parse::<i64>().ok()?);
+    whole.checked_add(numerator.checked_div(denominator)?)
+}
+
+/// The exact value of the decimal `buffer` followed by `repeating` forever. Shifting the point past
+/// one repetition and subtracting cancels the infinite tail, so `w.b(r)` is
+/// `(wbr - wb) / (10^len(b) * (10^len(r) - 1))`
+fn parse_repeating(buffer: &str, repeating: &str) -> Option<Value> {
+    let (whole, fixed) = buffer.split_once('.')?;
+    let digits = |s: &str| -> Option<i64> {
+        match s {
+            "" => Some(0),
+            _ => s.parse().ok(),
+        }
+    };
+    let shifted = digits(&format!("{}{}{}", whole, fixed, repeating))?;
+    let unshifted = digits(&format!("{}{}", whole, fixed))?;
+
+    let power = |n: usize| 10i64.checked_pow(u32::try_from(n).ok()?);
+    let denominator = power(fixed.len())?.checked_mul(power(repeating.len())? - 1)?;
+    Value::new_rational(shifted - unshifted, denominator).ok()
+}
+
+/// Integers are read exactly, anything with a point goes through `f64`
+#[cfg(not(feature = "decimal"))]
+fn parse_number(buffer: &str) -> Option<Value> {
+    match buffer.parse::<i64>() {
+        Ok(i) => Some(Value::Integer(i)),
+        Err(_) => buffer.parse().ok().and_then(Value::checked_from_f64),
+    }
+}
+
+/// Literals go through `Decimal` so they're exact in base 10, rather than through `f64`
+#[cfg(feature = "decimal")]
+fn parse_number(buffer: &str) -> Option<Value> {
+    rust_decimal::Decimal::from_str(buffer)
+        .ok()
+        .and_then(Value::checked_from_decimal)
+}
+
+/// A token stream for a source string that is kept up to date as the source is edited,
+/// only re-lexing from the edit onward
+/// ```rust
+/// # use rational_calculator::lex::{tokenize, Lexer};
+/// let mut lexer = Lexer::new("(10+5)").unwrap();
+/// lexer.edit(4..5, "7").unwrap();
+/// assert_eq!(lexer.tokens(), &tokenize("(10+7)")[..]);
+/// ```
+///
+/// An edit that leaves an invalid literal behind (`1.2.` while typing `1.2.3`) is reported as an
+/// error, the tokens before it are kept and the next edit picks up from them
+#[derive(Clone, Debug)]
+pub struct Lexer {
+    source: String,
+    tokens: Vec<Token>,
+    starts: Vec<usize>,
+}
+impl Lexer {
+    pub fn new(s: &str) -> Result<Self, LexError> {
+        let mut lexer = Lexer {
+            source: s.to_string(),
+            tokens: Vec::new(),
+            starts: Vec::new(),
+        };
+        lexer.relex_from(0)?;
+        Ok(lexer)
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// Replace the byte range `range` of the source with `text` and update the tokens
+    ///
+    /// Panics if `range` doesn't lie on char boundaries, as `String::replace_range` does
+    pub fn edit(&mut self, range: Range<usize>, text: &str) -> Result<&[Token], LexError> {
+        let offset = range.start;
+        self.source.replace_range(range, text);
+        self.relex_from(offset)
+    }
+
+    /// Re-lex the source after it changed at byte `offset`, every token before it is reused
+    pub fn relex_from(&mut self, offset: usize) -> Result<&[Token], LexError> {
+        // The token touching `offset` may grow into the edit (`1` -> `12`), so restart from it
+        let mut keep = self
+            .starts
+            .iter()
+            .take_while(|&&start| start < offset)
+            .count()
+            .saturating_sub(1);
+        // and from a decimal before the parens it touches, which may yet read as its repeating
+        // digits (`0.1(6` -> `0.1(6)`)
+        let open = match self.tokens.get(keep) {
+            Some(Token::Value(_)) => keep.saturating_sub(1),
+            _ => keep,
+        };
+        if open > 0
+            && self.tokens.get(open) == Some(&Token::Paren(Paren::Left))
+            && matches!(self.tokens[open - 1], Token::Value(_))
+        {
+            keep = open - 1;
+        }
+        // and from the first of the tokens read from the same characters (`²` is `^ 2`)
+        while keep > 0 && self.starts[keep - 1] == self.starts[keep] {
+            keep -= 1;
+        }
+        let restart = self.starts.get(keep).copied().unwrap_or(0);
+        // how a `|` or `,` is read depends on the bars and operator functions still open before it
+        let prefix = &self.source[..restart];
+        let restart = if prefix.contains('|')
+            || OPERATOR_FUNCTIONS
+                .iter()
+                .any(|&(name, _)| prefix.contains(&format!("{}(", name)))
+        {
+            0
+        } else {
+            restart
+        };
+        let keep = if restart == 0 { 0 } else { keep };
+
+        self.tokens.truncate(keep);
+        self.starts.truncate(keep);
+        lex(
+            &self.source,
+            restart,
+            Mode::Lenient,
+            &mut self.tokens,
+            &mut self.starts,
+        )?;
+
+        Ok(&self.tokens)
+    }
 }
 
-fn precedence(token: &Token) -> u32 {
+pub(crate) fn precedence(token: &Token) -> u32 {
     match token {
         Token::Operator(o) => match o {
             Operator::Add => 2,
             Operator::Sub => 2,
             Operator::Mul => 3,
             Operator::Div => 3,
+            Operator::FloorDiv => 3,
+            Operator::Mod => 3,
+            Operator::Pow => 4,
             Operator::USub => 5,
+            Operator::Cond => 1,
+            Operator::Else => 1,
         },
         _ => 0,
     }
 }
 
 #[derive(Copy, Clone, PartialEq)]
-enum OperatorAssociativity {
+pub(crate) enum OperatorAssociativity {
     Left,
     Right,
 }
 impl From<Token> for OperatorAssociativity {
     fn from(token: Token) -> Self {
         match token {
-            Token::Operator(Operator::USub) => OperatorAssociativity::Right,
+            Token::Operator(Operator::Pow)
+            | Token::Operator(Operator::USub)
+            | Token::Operator(Operator::Cond)
+            | Token::Operator(Operator::Else) => OperatorAssociativity::Right,
             _ => OperatorAssociativity::Left,
         }
     }
@@ -275,6 +1136,134 @@ where
     }
 }
 
+/// Rebuild an infix string from an infix token stream, the inverse of `tokenize` for well-formed streams
+///
+/// Binary operators are spaced, unary minus is written as a prefix `-`, and values that don't have
+/// an exact decimal form are written as a parenthesized fraction
+pub fn detokenize(tokens: &[Token]) -> String {
+    let mut output = String::new();
+
+    for token in tokens {
+        match token {
+            Token::Operator(Operator::USub) => output.push('-'),
+            Token::Operator(op) => {
+                output.push(' ');
+                output.push_str(op.to_str());
+                output.push(' ');
+            }
+            Token::Paren(paren) => output.push(paren.to_char()),
+            Token::Value(value) => output.push_str(&value_literal(*value)),
+            Token::Variable(name) | Token::Function(name) => output.push_str(name),
+        }
+    }
+
+    output
+}
+
+/// The shortest exact literal for `value`, a decimal if the denominator only has factors of 2 and 5.
+/// The non-finite values are written as the division that gives them, `(1/0)`
+fn value_literal(value: Value) -> String {
+    let Fraction {
+        numerator,
+        denominator,
+    } = value.as_fraction();
+    match denominator {
+        0 => return format!("({}/0)", numerator),
+        1 => return numerator.to_string(),
+        _ => (),
+    }
+
+    let (mut rest, mut twos, mut fives) = (denominator, 0, 0);
+    while rest % 2 == 0 {
+        rest /= 2;
+        twos += 1;
+    }
+    while rest % 5 == 0 {
+        rest /= 5;
+        fives += 1;
+    }
+    let scale = twos.max(fives);
+    let digits = 10i64
+        .checked_pow(scale)
+        .and_then(|power| numerator.checked_mul(power / denominator));
+
+    match digits {
+        Some(digits) if rest == 1 => {
+            let sign = if digits < 0 { "-" } else { "" };
+            let digits = format!(
+                "{:0>width$}",
+                digits.unsigned_abs(),
+                width = scale as usize + 1
+            );
+            let (whole, fractional) = digits.split_at(digits.len() - scale as usize);
+            format!("{}{}.{}", sign, whole, fractional)
+        }
+        _ => format!("({}/{})", numerator, denominator),
+    }
+}
+
+/// Unit suffixes and the multiplier they scale a number by
+pub const UNITS: &[(&str, i64)] = &[
+    ("k", 1_000),
+    ("M", 1_000_000),
+    ("G", 1_000_000_000),
+    ("T", 1_000_000_000_000),
+];
+
+/// Scale each value directly followed by a unit from `UNITS` by that unit's multiplier, `3k` -> `3000`
+///
+/// A unit that doesn't follow a value is left as a variable
+pub fn apply_units(tokens: Vec<Token>) -> Result<Vec<Token>, EvalError> {
+    let mut output: Vec<Token> = Vec::with_capacity(tokens.len());
+
+    for token in tokens {
+        let multiplier = match (&token, output.last()) {
+            (Token::Variable(name), Some(Token::Value(_))) => UNITS
+                .iter()
+                .find(|(unit, _)| unit == name)
+                .map(|&(_, multiplier)| multiplier),
+            _ => None,
+        };
+
+        match (multiplier, output.last_mut()) {
+            (Some(multiplier), Some(Token::Value(value))) => {
+                *value = value.try_mul(Value::from(multiplier))?;
+            }
+            _ => output.push(token),
+        }
+    }
+
+    Ok(output)
+}
+
+/// Insert a multiplication between adjacent operands of an infix token stream, so `2(3)` reads as `2*(3)`
+///
+/// Two adjacent numbers (`2 3`) are left alone, as they're more likely a typo than a product
+pub fn insert_implicit_mul(tokens: Vec<Token>) -> Vec<Token> {
+    let mut output: Vec<Token> = Vec::with_capacity(tokens.len());
+
+    for token in tokens {
+        let ends_operand = matches!(
+            output.last(),
+            Some(Token::Value(_)) | Some(Token::Variable(_)) | Some(Token::Paren(Paren::Right))
+        );
+        let starts_operand = match (&token, output.last()) {
+            (Token::Paren(Paren::Left), _) | (Token::Variable(_), _) | (Token::Function(_), _) => {
+                true
+            }
+            (Token::Value(_), Some(Token::Value(_))) => false,
+            (Token::Value(_), _) => true,
+            _ => false,
+        };
+        if ends_operand && starts_operand {
+            output.push(Token::Operator(Operator::Mul));
+        }
+        output.push(token);
+    }
+
+    output
+}
+
 /// Takes an infix notated token stream and converts it to postfix notation
 pub fn shunting_yard(tokens: Vec<Token>) -> Vec<Token> {
     // /*DEBUG:*/ eprintln!("Begin reverse poilsh conversion");
@@ -285,10 +1274,28 @@ pub fn shunting_yard(tokens: Vec<Token>) -> Vec<Token> {
         // /*DEBUG:*/ eprintln!("\nCurrent state:\n\tOperator stack: {:?}\n\tOutput: {:?}", opstack, output);
         // /*DEBUG:*/ eprint!("Encountered {:?} -> ", token);
         match token {
-            Token::Value(_v) => {
-                // /*DEBUG:*/ eprintln!("pushing token with value {} to the output", _v);
+            Token::Value(_) | Token::Variable(_) => {
+                // /*DEBUG:*/ eprintln!("pushing token {} to the output", token);
                 output.push(token);
             }
+            // waits on the stack for its closing paren, like an operator that binds tighter than any other
+            Token::Function(_) => opstack.push(token),
+            // a `:` closes the innermost `?` that doesn't have one yet, popping everything above it
+            Token::Operator(Operator::Else) => {
+                let mut popped_else = false;
+                while let Some(top) = opstack.last() {
+                    match top {
+                        Token::Paren(_) => break,
+                        // a `?` directly under a `:` that was just popped already has its `:`
+                        Token::Operator(Operator::Cond) if !popped_else => break,
+                        o => {
+                            popped_else = *o == Token::Operator(Operator::Else);
+                            output.push(opstack.pop().unwrap());
+                        }
+                    }
+                }
+                opstack.push(token);
+            }
             Token::Operator(_op) => {
                 let p = precedence(&token);
                 // /*DEBUG:*/ eprintln!("Operator {:?} -> Popping tokens from stack: ", _op);
@@ -300,6 +1307,9 @@ pub fn shunting_yard(tokens: Vec<Token>) -> Vec<Token> {
                         }
                         Some(o) => {
                             // /*DEBUG:*/ eprint!("\tEncountered operator {} -> ", o);
+                            // everything left on the stack under the new operator binds looser than it, or
+                            // exactly as tight for a right-associative one: pop while the top binds tighter
+                            // (or equally tight when left-associative, so `10-3-2` is `(10-3)-2`)
                             if match OperatorAssociativity::from(&token) {
                                 OperatorAssociativity::Left => {
                                     // /*DEBUG:*/ eprint!("looking for precedence({}) < {}...", o, p);
@@ -320,14 +1330,14 @@ pub fn shunting_yard(tokens: Vec<Token>) -> Vec<Token> {
                         _ => unreachable!(),
                     }
                 }
-                opstack.push(token.clone());
+                opstack.push(token);
             }
             Token::Paren(p) => {
                 // /*DEBUG:*/ eprint!("Encountered paren -> ");
                 match p {
                     Paren::Left => {
                         // /*DEBUG:*/ eprintln!("Left paren, push to operator stack");
-                        opstack.push(token.clone())
+                        opstack.push(token)
                     }
                     Paren::Right => {
                         // /*DEBUG:*/ eprintln!("Right paren, popping operator stack to output until we see a left paren");
@@ -347,6 +1357,9 @@ pub fn shunting_yard(tokens: Vec<Token>) -> Vec<Token> {
                                 unreachable!()
                             }
                         }
+                        if let Some(Token::Function(_)) = opstack.last() {
+                            output.push(opstack.pop().unwrap());
+                        }
                     }
                 }
             }
@@ -364,6 +1377,11 @@ pub fn shunting_yard(tokens: Vec<Token>) -> Vec<Token> {
     output
 }
 
+#[cfg(test)]
+fn fraction(numerator: i64, denominator: i64) -> Value {
+    Value::new_rational(numerator, denominator).unwrap()
+}
+
 #[test]
 fn test_tokenize() {
     // Basic
@@ -448,6 +1466,7 @@ fn test_shunting_yard() {
 }
 
 #[test]
+#[allow(clippy::identity_op)]
 fn test_operator_evaluate() {
     assert_eq!(
         Operator::Add.evaluate(1.into(), 10.into()),
@@ -511,3 +1530,701 @@ fn test_operator_evaluate() {
         Value::from(-10)
     );
 }
+
+#[test]
+fn test_lexer_edit() {
+    // (source, edited range, replacement)
+    let edits = vec![
+        ("(10+5)", 4..5, "7"),
+        ("(10+5)", 2..2, "0"),
+        ("10+5", 2..3, "-"),
+        ("10+5", 3..3, "-"),
+        ("10 - 5", 3..4, "*"),
+        ("1-2", 1..2, ""),
+        ("12 * (3 + 4)", 0..1, "-"),
+        ("12 * (3 + 4)", 11..12, ""),
+        ("2^3", 3..3, ".5"),
+        ("5²", 3..3, "+1"),
+        ("5² + 1", 6..7, "2"),
+        ("2³²", 5..5, "1"),
+        ("|1| + 2", 6..7, "3"),
+        ("add(1, 2)", 8..8, "+3"),
+        ("0.1(6", 5..5, ")"),
+        ("0.1(", 4..4, "6)"),
+        ("0.1(6)", 5..6, ""),
+        ("2 + 0.1(66)", 10..10, "6"),
+    ];
+
+    for (source, range, text) in edits {
+        let mut edited = source.to_string();
+        edited.replace_range(range.clone(), text);
+
+        let mut lexer = Lexer::new(source).unwrap();
+        assert_eq!(lexer.tokens(), &tokenize(source)[..]);
+        assert_eq!(lexer.edit(range, text), Ok(&tokenize(&edited)[..]));
+        assert_eq!(lexer.source(), edited);
+    }
+
+    assert_eq!(
+        Lexer::new("3_").map(|lexer| lexer.tokens().to_vec()),
+        try_tokenize("3_")
+    );
+    // the intermediate states of typing `1.2.3`
+    assert_eq!(
+        Lexer::new("1.2.").unwrap_err(),
+        LexError::InvalidNumber("1.2.".to_string())
+    );
+    let mut lexer = Lexer::new("1 + 1.2").unwrap();
+    assert_eq!(
+        lexer.edit(7..7, ".").unwrap_err(),
+        LexError::InvalidNumber("1.2.".to_string())
+    );
+    assert_eq!(
+        lexer.edit(8..8, "3").unwrap_err(),
+        try_tokenize("1 + 1.2.3").unwrap_err()
+    );
+    assert_eq!(lexer.edit(5..8, ""), Ok(&tokenize("1 + 13")[..]));
+}
+
+#[test]
+fn test_tokenize_with_history() {
+    let history = vec![Value::from(12), Value::from(0.5)];
+
+    assert_eq!(
+        tokenize_with_history("$1 + $2", &history),
+        Ok(vec![
+            Token::Value(12.into()),
+            Token::new("+"),
+            Token::Value(0.5.into())
+        ])
+    );
+    // $n keeps the unary minus context of the surrounding tokens
+    assert_eq!(
+        tokenize_with_history("$1 - -$2", &history),
+        Ok(vec![
+            Token::Value(12.into()),
+            Token::new("-"),
+            Token::new("u"),
+            Token::Value(0.5.into())
+        ])
+    );
+    assert_eq!(
+        tokenize_with_history("(1 + 2) * 3", &history),
+        Ok(tokenize("(1 + 2) * 3"))
+    );
+
+    assert_eq!(
+        tokenize_with_history("$3 * 2", &history),
+        Err(EvalError::HistoryOutOfRange(3))
+    );
+    assert_eq!(
+        tokenize_with_history("$0", &history),
+        Err(EvalError::HistoryOutOfRange(0))
+    );
+
+    // `$n` is read in the same pass as the rest, so bars and operator functions span it
+    assert_eq!(
+        tokenize_with_history("|$1|", &[Value::from(3)]),
+        Ok(tokenize("|3|"))
+    );
+    assert_eq!(
+        tokenize_with_history("|-$1| * |$1|", &[Value::from(3)]),
+        Ok(tokenize("|-3| * |3|"))
+    );
+    assert_eq!(
+        tokenize_with_history("add($1, 2)", &[Value::from(3)]),
+        Ok(tokenize("add(3, 2)"))
+    );
+    assert_eq!(
+        tokenize_with_history("mul($1, $2)", &history),
+        Ok(vec![
+            Token::new("("),
+            Token::new("("),
+            Token::Value(12.into()),
+            Token::new(")"),
+            Token::new("*"),
+            Token::new("("),
+            Token::Value(0.5.into()),
+            Token::new(")"),
+            Token::new(")"),
+        ])
+    );
+}
+
+#[test]
+fn test_tokenize_variables() {
+    let tokens = vec![
+        Token::new("x"),
+        Token::new("^"),
+        Token::new("2"),
+        Token::new("+"),
+        Token::new("rate"),
+        Token::new("-"),
+        Token::new("u"),
+        Token::new("y"),
+    ];
+    assert_eq!(tokenize("x^2 + rate - -y"), tokens);
+    assert_eq!(Token::new("rate"), Token::Variable("rate".to_string()));
+
+    // a number directly followed by a name is two tokens
+    assert_eq!(tokenize("2x"), vec![Token::new("2"), Token::new("x")]);
+}
+
+#[cfg(feature = "decimal")]
+#[test]
+fn test_tokenize_decimal() {
+    // 18 digits after the point is past what survives a trip through f64
+    assert_eq!(
+        tokenize("0.123456789012345678"),
+        vec![Token::Value(
+            Value::from(123456789012345678) / Value::from(1000000000000000000)
+        )]
+    );
+    assert_eq!(
+        crate::tree::Tree::new("0.1+0.2").evaluate(),
+        Value::from(3) / Value::from(10)
+    );
+}
+
+#[test]
+fn test_tokenize_floor_div() {
+    assert_eq!(
+        tokenize("7 // 2"),
+        vec![Token::new("7"), Token::new("//"), Token::new("2")]
+    );
+    assert_eq!(Token::new("//"), Token::Operator(Operator::FloorDiv));
+    assert_eq!(Operator::FloorDiv.to_string(), "//");
+
+    // separated slashes are still two divisions
+    assert_eq!(
+        tokenize("8 / / 2"),
+        vec![
+            Token::new("8"),
+            Token::new("/"),
+            Token::new("/"),
+            Token::new("2")
+        ]
+    );
+}
+
+#[test]
+fn test_insert_implicit_mul() {
+    let with_mul = |s: &str| insert_implicit_mul(tokenize(s));
+
+    assert_eq!(with_mul("2(3)"), tokenize("2*(3)"));
+    assert_eq!(with_mul("(1+2)(3-4)"), tokenize("(1+2)*(3-4)"));
+    assert_eq!(with_mul("2x + (x)3"), tokenize("2*x + (x)*3"));
+    assert_eq!(with_mul("2 3"), tokenize("2 3"));
+    assert_eq!(with_mul("-2 * -(3)"), tokenize("-2 * -(3)"));
+}
+
+#[test]
+fn test_detokenize() {
+    let source = detokenize(&tokenize("-10 + 5"));
+    assert_eq!(source, "-10 + 5");
+    assert_eq!(tokenize(&source), tokenize("-10 + 5"));
+
+    let round_trip = |s: &str| tokenize(&detokenize(&tokenize(s))) == tokenize(s);
+    assert!(round_trip("((10*2)/4+(2*4)*2)"));
+    assert!(round_trip("2^-x // 0.125 % --3.5"));
+    assert!(round_trip("0.05 * 12.375"));
+
+    assert_eq!(detokenize(&tokenize("(1+2)*-3")), "(1 + 2) * -3");
+    assert_eq!(detokenize(&tokenize("x%0.025")), "x % 0.025");
+    assert_eq!(
+        detokenize(&[Token::Value(Value::from(1) / Value::from(3))]),
+        "(1/3)"
+    );
+    assert_eq!(
+        detokenize(&[
+            Token::Value(Value::NegInfinity),
+            Token::new("+"),
+            Token::new("1")
+        ]),
+        "(-1/0) + 1"
+    );
+}
+
+#[test]
+fn test_tokenize_mixed_number() {
+    let seven_halves = Token::Value(Value::from(7) / Value::from(2));
+
+    assert_eq!(tokenize("3_1/2"), vec![seven_halves.clone()]);
+    assert_eq!(
+        tokenize("-3_1/2*2"),
+        vec![
+            Token::new("u"),
+            seven_halves,
+            Token::new("*"),
+            Token::new("2")
+        ]
+    );
+    assert_eq!(tokenize("3_2/4"), tokenize("3.5"));
+    assert_eq!(tokenize("3_1/2/7"), tokenize("3.5/7"));
+
+    // spaces break the literal apart
+    assert_eq!(tokenize("3 1/2"), tokenize("31/2"));
+    assert_eq!(
+        try_tokenize("3_1 /2"),
+        Err(LexError::InvalidNumber("3_1".to_string()))
+    );
+
+    assert_eq!(
+        try_tokenize("3_1/0"),
+        Err(LexError::InvalidNumber("3_1/0".to_string()))
+    );
+}
+
+#[test]
+fn test_tokenize_strict() {
+    // the lenient tokenizer reads `x` as an operand with no operator on either side of it, and joins
+    // the digits of `1 2` into `12`
+    assert_eq!(
+        tokenize("2 x 3"),
+        vec![
+            Token::new("2"),
+            Token::Variable("x".to_string()),
+            Token::new("3")
+        ]
+    );
+    assert_eq!(tokenize_strict("2 x 3"), Err(LexError::MissingOperator(2)));
+    assert_eq!(tokenize("1 2"), tokenize("12"));
+    assert_eq!(tokenize_strict("1 2"), Err(LexError::MissingOperator(2)));
+
+    // unknown characters are dropped
+    assert_eq!(tokenize("2 # 3"), tokenize("23"));
+    assert_eq!(
+        tokenize_strict("2 # 3"),
+        Err(LexError::UnexpectedChar('#', 2))
+    );
+    assert_eq!(tokenize("1.5_2"), vec![Token::new("1.5"), Token::new("2")]);
+    assert_eq!(
+        tokenize_strict("1.5_2"),
+        Err(LexError::UnexpectedChar('_', 3))
+    );
+
+    assert_eq!(
+        tokenize("--5"),
+        vec![Token::new("u"), Token::new("u"), Token::new("5")]
+    );
+    assert_eq!(tokenize_strict("--5"), Err(LexError::AmbiguousUnary(1)));
+    // only the first minus after an operand is a subtraction, every one after that is unary
+    assert_eq!(
+        tokenize("3 - - - 2"),
+        vec![
+            Token::new("3"),
+            Token::new("-"),
+            Token::new("u"),
+            Token::new("u"),
+            Token::new("2")
+        ]
+    );
+
+    for s in &["(10 + 5) * -2", "3_1/2 // x ^ -y", "1.25 % 2"] {
+        assert_eq!(tokenize_strict(s), Ok(tokenize(s)));
+    }
+}
+
+#[test]
+fn test_tokenize_function() {
+    let tokens = tokenize("double(21) + x (2)");
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Function("double".to_string()),
+            Token::new("("),
+            Token::new("21"),
+            Token::new(")"),
+            Token::new("+"),
+            Token::Variable("x".to_string()),
+            Token::new("("),
+            Token::new("2"),
+            Token::new(")"),
+        ]
+    );
+    assert_eq!(detokenize(&tokenize("f(g(1) * 2)")), "f(g(1) * 2)");
+
+    assert_eq!(
+        shunting_yard(tokenize("f(1 + 2) * 3")),
+        vec![
+            Token::new("1"),
+            Token::new("2"),
+            Token::new("+"),
+            Token::Function("f".to_string()),
+            Token::new("3"),
+            Token::new("*"),
+        ]
+    );
+}
+
+#[test]
+fn test_tokenize_recover() {
+    assert_eq!(
+        tokenize_recover("2 @ 3"),
+        (
+            vec![Token::new("2"), Token::new("3")],
+            vec![LexError::UnexpectedChar('@', 2)]
+        )
+    );
+    assert_eq!(
+        tokenize_recover("1.2.3 + 4 # (5)"),
+        (
+            // with the literal dropped the `+` leads the expression, so it's a unary plus
+            vec![
+                Token::new("4"),
+                Token::new("("),
+                Token::new("5"),
+                Token::new(")")
+            ],
+            vec![
+                LexError::InvalidNumber("1.2.3".to_string()),
+                LexError::UnexpectedChar('#', 10)
+            ]
+        )
+    );
+    assert_eq!(tokenize_recover("(10+5)"), (tokenize("(10+5)"), vec![]));
+}
+
+#[test]
+fn test_operator_paren_display() {
+    assert_eq!(format!("{}", Operator::Add), "+");
+    assert_eq!(format!("{}", Operator::FloorDiv), "//");
+    assert_eq!(Operator::Pow.to_string(), "^");
+    assert_eq!(format!("{}{}", Paren::Left, Paren::Right), "()");
+    assert_eq!(Token::Operator(Operator::Mod).to_string(), "%");
+}
+
+#[test]
+fn test_tokenize_explicit() {
+    assert_eq!(tokenize("2 3"), tokenize("23"));
+    assert_eq!(tokenize_explicit("2 3"), Err(LexError::MissingOperator(2)));
+    assert_eq!(
+        tokenize_explicit("(1)(2)"),
+        Err(LexError::MissingOperator(3))
+    );
+    assert_eq!(
+        tokenize_explicit("2 (3)"),
+        Err(LexError::MissingOperator(2))
+    );
+    assert_eq!(tokenize_explicit("(3)x"), Err(LexError::MissingOperator(3)));
+
+    // everything else is as lenient as ever
+    assert_eq!(tokenize_explicit("2 # * f(3)"), Ok(tokenize("2*f(3)")));
+    assert_eq!(tokenize_explicit("--5"), Ok(tokenize("--5")));
+}
+
+#[test]
+fn test_shunting_yard_conditional() {
+    let postfix = |s: &str| {
+        shunting_yard(tokenize(s))
+            .iter()
+            .map(|token| token.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    assert_eq!(postfix("1 ? 10 : 20"), "1 10 20 : ?");
+    assert_eq!(postfix("1 + 2 ? 3 * 4 : 5 - 6"), "1 2 + 3 4 * 5 6 - : ?");
+    assert_eq!(postfix("a ? b : c ? d : e"), "a b c d e : ? : ?");
+    assert_eq!(postfix("a ? b ? c : d : e"), "a b c d : ? e : ?");
+    assert_eq!(postfix("(a ? b : c) ? d : e"), "a b c : ? d e : ?");
+}
+
+#[test]
+fn test_tokenizer_reuse() {
+    let mut tokenizer = Tokenizer::new();
+    let mut tokens = Vec::new();
+
+    for s in &[
+        "(10+5)",
+        "-3.25 * x",
+        "3_1/2 // 2",
+        "max(1 ? 2 : 3)",
+        "",
+        "2 ^ -1",
+    ] {
+        tokenizer.tokenize_into(s, &mut tokens).unwrap();
+        assert_eq!(tokens, tokenize(s));
+    }
+    assert_eq!(
+        tokenizer.tokenize_into("1.2.3", &mut tokens),
+        Err(LexError::InvalidNumber("1.2.3".to_string()))
+    );
+    tokenizer.tokenize_into("7", &mut tokens).unwrap();
+    assert_eq!(tokens, vec![Token::Value(Value::from(7))]);
+}
+
+#[test]
+fn test_tokenize_superscript() {
+    let pow = |base: Token, exponent: i64| {
+        vec![
+            base,
+            Token::Operator(Operator::Pow),
+            Token::Value(Value::from(exponent)),
+        ]
+    };
+
+    assert_eq!(tokenize("5²"), pow(Token::Value(Value::from(5)), 2));
+    assert_eq!(tokenize("x³"), pow(Token::Variable("x".to_string()), 3));
+    assert_eq!(tokenize("2¹⁰"), pow(Token::Value(Value::from(2)), 10));
+    assert_eq!(
+        tokenize("(1+1)²")[4..],
+        pow(Token::Paren(Paren::Right), 2)[..]
+    );
+    assert_eq!(tokenize_strict("5²"), Ok(tokenize("5^2")));
+    assert_eq!(
+        tokenize("2²²²²²²²²²²²²²²²²²²²²"),
+        pow(Token::Value(Value::from(2)), i64::MAX - 1)
+    );
+    assert_eq!(
+        tokenize("2²²²²²²²²²²²²²²²²²²²³"),
+        pow(Token::Value(Value::from(2)), i64::MAX)
+    );
+}
+
+#[test]
+fn test_tokenize_unary_plus() {
+    let value = |i: i64| Token::Value(Value::from(i));
+
+    assert_eq!(tokenize("+5"), vec![value(5)]);
+    assert_eq!(tokenize("3 * +2"), tokenize("3 * 2"));
+    assert_eq!(tokenize("(+1)"), tokenize("(1)"));
+    assert_eq!(tokenize("-+4"), tokenize("-4"));
+    // still a binary add after an operand
+    assert_eq!(
+        tokenize("1 + +2"),
+        vec![value(1), Token::Operator(Operator::Add), value(2)]
+    );
+}
+
+#[test]
+fn test_operator_try_evaluate() {
+    assert_eq!(
+        Operator::Div.try_evaluate(1.into(), 0.into()),
+        Err(EvalError::DivideByZero)
+    );
+    assert_eq!(
+        Operator::Div.try_evaluate(1.into(), 4.into()),
+        Ok(Value::from(0.25))
+    );
+    assert_eq!(
+        Operator::Mod.try_evaluate(5.into(), 0.into()),
+        Err(EvalError::DivideByZero)
+    );
+    assert_eq!(
+        Operator::Pow.try_evaluate(0.into(), (-1).into()),
+        Err(EvalError::DivideByZero)
+    );
+    assert_eq!(
+        Operator::Pow.try_evaluate(2.into(), 0.5.into()),
+        Err(EvalError::NotAnInteger)
+    );
+    assert_eq!(
+        Operator::Mul.try_evaluate(i64::MAX.into(), 2.into()),
+        Err(EvalError::Overflow)
+    );
+}
+
+#[test]
+fn test_tokenize_abs_bars() {
+    let abs = || Token::Function("abs".to_string());
+
+    assert_eq!(
+        tokenize("|−5|"),
+        vec![
+            abs(),
+            Token::new("("),
+            Token::new("u"),
+            Token::new("5"),
+            Token::new(")")
+        ]
+    );
+    assert_eq!(tokenize("|3−7|"), tokenize("abs(3-7)"));
+    assert_eq!(tokenize("|a| * |b|"), tokenize("abs(a) * abs(b)"));
+    assert_eq!(tokenize("||-5| - 3|"), tokenize("abs(abs(-5) - 3)"));
+    assert_eq!(tokenize("2 * |x - 1|"), tokenize("2 * abs(x - 1)"));
+    assert_eq!(tokenize_explicit("2|x|"), Err(LexError::MissingOperator(1)));
+}
+
+#[test]
+fn test_tokenize_locale() {
+    let comma = LocaleOptions {
+        decimal_comma: true,
+    };
+    let point = LocaleOptions::default();
+
+    assert_eq!(tokenize_with_locale("3,5", comma), Ok(tokenize("3.5")));
+    assert_eq!(tokenize_with_locale("3,5", point), Ok(tokenize("35")));
+    assert_eq!(
+        tokenize_with_locale("3,5 + 1,5", comma),
+        Ok(tokenize("3.5 + 1.5"))
+    );
+    assert_eq!(
+        tokenize_with_locale("1.000,25", comma),
+        Ok(tokenize("1000.25"))
+    );
+    assert_eq!(
+        tokenize_with_locale("1,000.25", point),
+        Ok(tokenize("1000.25"))
+    );
+    assert_eq!(
+        tokenize_explicit_with_locale("2,5 3", comma),
+        Err(LexError::MissingOperator(4))
+    );
+
+    let mut tokenizer = Tokenizer::with_locale(comma);
+    let mut out = Vec::new();
+    tokenizer.tokenize_into("-0,25", &mut out).unwrap();
+    assert_eq!(out, tokenize("-0.25"));
+}
+
+#[test]
+fn test_tokenize_double_star_pow() {
+    assert_eq!(
+        tokenize("2**3"),
+        vec![Token::new("2"), Token::new("^"), Token::new("3")]
+    );
+    assert_eq!(tokenize("2*3"), tokenize("2 * 3"));
+    assert_eq!(Token::new("**"), Token::Operator(Operator::Pow));
+    assert_eq!(
+        tokenize("2***3"),
+        vec![
+            Token::new("2"),
+            Token::new("^"),
+            Token::new("*"),
+            Token::new("3")
+        ]
+    );
+    // separated stars are still two multiplications
+    assert_eq!(
+        tokenize("2* *3"),
+        vec![
+            Token::new("2"),
+            Token::new("*"),
+            Token::new("*"),
+            Token::new("3")
+        ]
+    );
+}
+
+#[test]
+fn test_token_from_literal() {
+    let expected: Result<Vec<Token>, LexError> = ["2", "*", "(", "u", "x", "//", "3", ")"]
+        .iter()
+        .map(|literal| Token::from_literal(literal))
+        .collect();
+    assert_eq!(expected, Ok(tokenize("2 * (-x // 3)")));
+    assert_eq!(
+        Token::from_literal("0.5"),
+        Ok(Token::Value(Value::from(0.5)))
+    );
+
+    assert_eq!(
+        Token::from_literal("#"),
+        Err(LexError::InvalidNumber("#".to_string()))
+    );
+    assert_eq!(
+        Token::from_literal(""),
+        Err(LexError::InvalidNumber(String::new()))
+    );
+}
+
+#[test]
+fn test_tokenize_lookahead() {
+    let literals = |s: &str| s.split(' ').map(Token::new).collect::<Vec<_>>();
+
+    // single characters directly after a number or identifier end it and are then read on their own
+    assert_eq!(tokenize("12+x*3"), literals("12 + x * 3"));
+    assert_eq!(tokenize("(1.5)-2"), literals("( 1.5 ) - 2"));
+    assert_eq!(tokenize("x-y"), literals("x - y"));
+    assert_eq!(tokenize("4-"), literals("4 -"));
+    assert_eq!(tokenize("4--3"), literals("4 - u 3"));
+
+    // as are two character operators, without swallowing what follows them
+    assert_eq!(tokenize("7//2"), literals("7 // 2"));
+    assert_eq!(tokenize("x**-2"), literals("x ^ u 2"));
+    assert_eq!(tokenize("3_1/2//2"), tokenize("3_1/2 // 2"));
+    assert_eq!(tokenize("8///2"), literals("8 // / 2"));
+    assert_eq!(tokenize("2*/3"), literals("2 * / 3"));
+    // the second character has to be adjacent
+    assert_eq!(tokenize("2* *3"), literals("2 * * 3"));
+    assert_eq!(tokenize("2**"), literals("2 ^"));
+}
+
+#[test]
+fn test_tokenize_operator_functions() {
+    assert_eq!(tokenize("add(2,3)"), tokenize("((2)+(3))"));
+    assert_eq!(tokenize("div(1, 4)"), tokenize("((1)/(4))"));
+    assert_eq!(
+        tokenize("2 * pow(-x, 1 + 1)"),
+        tokenize("2 * ((-x)^(1 + 1))")
+    );
+    assert_eq!(
+        tokenize("sub(mul(2, 3), (4))"),
+        tokenize("((((2)*(3)))-((4)))")
+    );
+    // outside of a call the comma is dropped as before
+    assert_eq!(tokenize("1,000 + add(1, 2)"), tokenize("1000 + ((1)+(2))"));
+
+    let comma = LocaleOptions {
+        decimal_comma: true,
+    };
+    assert_eq!(
+        tokenize_with_locale("add(1,5; 2)", comma),
+        Ok(tokenize("((1.5)+(2))"))
+    );
+
+    assert_eq!(
+        try_tokenize("1 + add(2)"),
+        Err(LexError::ExpectedTwoArguments(4))
+    );
+    assert_eq!(
+        try_tokenize("add(1, 2, 3)"),
+        Err(LexError::ExpectedTwoArguments(0))
+    );
+    assert_eq!(
+        tokenize_strict("1,000"),
+        Err(LexError::UnexpectedChar(',', 1))
+    );
+
+    let mut lexer = Lexer::new("add(1, 2) + 3").unwrap();
+    lexer.edit(12..13, "4").unwrap();
+    assert_eq!(lexer.tokens(), &tokenize("add(1, 2) + 4")[..]);
+}
+
+#[test]
+fn test_tokenize_repeating_decimal() {
+    assert_eq!(tokenize("0.(3)"), vec![Token::Value(fraction(1, 3))]);
+    assert_eq!(tokenize("0.1(6)"), vec![Token::Value(fraction(1, 6))]);
+    assert_eq!(tokenize(".(142857)"), vec![Token::Value(fraction(1, 7))]);
+    assert_eq!(tokenize("1.(9)"), vec![Token::Value(fraction(2, 1))]);
+    assert_eq!(tokenize("2.25(0)"), vec![Token::Value(fraction(9, 4))]);
+    assert_eq!(
+        tokenize("-3.1(45) * 2"),
+        vec![
+            Token::new("u"),
+            Token::Value(fraction(173, 55)),
+            Token::new("*"),
+            Token::new("2")
+        ]
+    );
+
+    // anything else after the point is a parenthesized expression as before
+    assert_eq!(
+        tokenize("0.5 (3)"),
+        vec![
+            Token::new("0.5"),
+            Token::new("("),
+            Token::new("3"),
+            Token::new(")")
+        ]
+    );
+    assert_eq!(tokenize("0.5(x)")[1], Token::new("("));
+    assert_eq!(tokenize("5(3)")[1], Token::new("("));
+    assert_eq!(
+        try_tokenize("0.(99999999999999999999)"),
+        Err(LexError::InvalidNumber(
+            "0.(99999999999999999999)".to_string()
+        ))
+    );
+}
@@ -0,0 +1,7 @@
+pub mod config;
+pub mod error;
+pub mod lex;
+pub mod tree;
+pub mod value;
+pub use error::*;
+pub use value::*;
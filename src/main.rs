@@ -1,12 +1,15 @@
-mod lex;
-mod tree;
-mod value;
+use rational_calculator::{
+    lex::{shunting_yard, tokenize_with_history},
+    tree::{parse_where, Tree},
+    EvalError, Value,
+};
 use std::io::Write;
-use value::*;
 
-fn calc(s: &String) -> Value {
-    let tree = tree::Tree::new(&s);
-    tree.evaluate()
+fn calc(s: &str, history: &[(String, Value)]) -> Result<Value, EvalError> {
+    let (expression, vars) = parse_where(s)?;
+    let results = history.iter().map(|(_, v)| *v).collect::<Vec<Value>>();
+    let tree = Tree::try_from_tokens(shunting_yard(tokenize_with_history(expression, &results)?))?;
+    tree.evaluate_with(&vars)
 }
 
 fn main() {
@@ -15,12 +18,46 @@ fn main() {
     let input = std::io::stdin();
     let mut output = std::io::stdout();
     let mut buffer = String::new();
+    let mut history: Vec<(String, Value)> = Vec::new();
+    // `:both` toggles printing the decimal form alongside the exact result
+    let mut both = false;
     println!("Enter an expression");
     loop {
         print!(">> ");
         output.flush().unwrap();
-        input.read_line(&mut buffer).unwrap();
-        println!("Result: {}", calc(&buffer));
+        if input.read_line(&mut buffer).unwrap() == 0 {
+            break;
+        }
+        let expression = buffer.trim().to_string();
         buffer.clear();
+
+        if expression.is_empty() {
+            continue;
+        } else if expression == ":history" {
+            for (idx, (expression, result)) in history.iter().enumerate() {
+                println!("${}: {} = {}", idx + 1, expression, result);
+            }
+            continue;
+        } else if expression == ":both" {
+            both = !both;
+            println!("Showing decimals: {}", both);
+            continue;
+        }
+
+        match calc(&expression, &history) {
+            Ok(result) => {
+                history.push((expression, result));
+                if both {
+                    println!(
+                        "Result (${}): {}",
+                        history.len(),
+                        result.to_exact_and_decimal()
+                    );
+                } else {
+                    println!("Result (${}): {}", history.len(), result);
+                }
+            }
+            Err(e) => println!("Error: {}", e),
+        }
     }
 }
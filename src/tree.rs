@@ -1,9 +1,19 @@
-use crate::lex::{shunting_yard, tokenize, Operator, Token};
-use crate::value::Value;
+use crate::config::{config, DisplayStyle};
+use crate::lex::{
+    apply_units, insert_implicit_mul, precedence, shunting_yard, tokenize_explicit_with_locale,
+    tokenize_implicit_with_locale, tokenize_with_locale, LocaleOptions, Operator,
+    OperatorAssociativity, Token,
+};
+use crate::value::{Modulo, Value};
+use crate::{EvalError, LexError};
 
-#[cfg(test)]
-use std::collections::HashMap;
-use std::fmt::{Debug, Display, Formatter, Result as fmt_Result};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt::{Debug, Display, Formatter, Result as fmt_Result},
+    io::{BufRead, BufReader},
+    time::{Duration, Instant},
+};
 
 #[derive(Clone)]
 struct Node {
@@ -20,36 +30,768 @@ impl Node {
         }
     }
 
-    fn evaluate(&self) -> Value {
-        match self.token {
-            Token::Value(v) => v,
+    fn evaluate(
+        &self,
+        vars: &HashMap<String, Value>,
+        functions: &FunctionRegistry,
+        mode: EvalMode,
+    ) -> Result<Value, EvalError> {
+        self.evaluate_before(vars, functions, mode, None)
+    }
+
+    /// `evaluate`, giving up with `EvalError::Timeout` if a node is reached after `deadline`
+    fn evaluate_before(
+        &self,
+        vars: &HashMap<String, Value>,
+        functions: &FunctionRegistry,
+        mode: EvalMode,
+        deadline: Option<Instant>,
+    ) -> Result<Value, EvalError> {
+        self.evaluate_noting(vars, functions, mode, deadline, &mut false)
+    }
+
+    /// `evaluate_before`, setting `inexact_division` if a `/` on the way produces a fraction
+    ///
+    /// The tree is walked on a stack of its own rather than by recursing, a long chain like
+    /// `1+1+...+1` is as deep as it is long
+    fn evaluate_noting(
+        &self,
+        vars: &HashMap<String, Value>,
+        functions: &FunctionRegistry,
+        mode: EvalMode,
+        deadline: Option<Instant>,
+        inexact_division: &mut bool,
+    ) -> Result<Value, EvalError> {
+        enum Step<'a> {
+            Visit(&'a Node),
+            /// combine the values on top of the value stack with this node's operator
+            Apply(&'a Node),
+            Call(Function),
+        }
+        fn child(node: &Option<Box<Node>>) -> &Node {
+            node.as_ref()
+                .expect("Something went wrong! (evaluate operator node with missing child)")
+        }
+
+        let mut steps = vec![Step::Visit(self)];
+        let mut values: Vec<Value> = Vec::new();
+        let pop = |values: &mut Vec<Value>| {
+            values
+                .pop()
+                .expect("Something went wrong! (operator applied to too few values)")
+        };
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Visit(node) => {
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        return Err(EvalError::Timeout);
+                    }
+                    match &node.token {
+                        Token::Value(v) => values.push(*v),
+                        Token::Variable(name) => values.push(
+                            vars.get(name)
+                                .copied()
+                                .or_else(|| constant_value(name))
+                                .ok_or_else(|| EvalError::UnknownVariable(name.clone()))?,
+                        ),
+                        Token::Function(name) => {
+                            let function = lookup(name, functions)
+                                .ok_or_else(|| EvalError::UnknownFunction(name.clone()))?;
+                            steps.push(Step::Call(function));
+                            steps.push(Step::Visit(child(&node.right)));
+                        }
+                        // only the selected branch is evaluated, once the condition is known
+                        Token::Operator(Operator::USub) | Token::Operator(Operator::Cond) => {
+                            steps.push(Step::Apply(node));
+                            let operand = match node.token {
+                                Token::Operator(Operator::Cond) => &node.left,
+                                _ => &node.right,
+                            };
+                            steps.push(Step::Visit(child(operand)));
+                        }
+                        Token::Operator(_) => {
+                            steps.push(Step::Apply(node));
+                            steps.push(Step::Visit(child(&node.right)));
+                            steps.push(Step::Visit(child(&node.left)));
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+                Step::Call(function) => {
+                    let argument = pop(&mut values);
+                    values.push(function(argument)?);
+                }
+                Step::Apply(node) => {
+                    let right = pop(&mut values);
+                    match node.token {
+                        Token::Operator(Operator::USub) => {
+                            values.push(right.checked_neg().ok_or(EvalError::Overflow)?)
+                        }
+                        Token::Operator(Operator::Cond) => {
+                            steps.push(Step::Visit(node.branch(right != 0)))
+                        }
+                        Token::Operator(op) => {
+                            let left = pop(&mut values);
+                            let result = op.try_evaluate_with_mode(left, right, mode)?;
+                            if op == Operator::Div && matches!(result, Value::Rational { .. }) {
+                                *inexact_division = true;
+                            }
+                            values.push(result);
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        }
+
+        Ok(pop(&mut values))
+    }
+
+    /// Evaluate the subtree with every intermediate result an `i64`, a step that comes out
+    /// fractional is an `EvalError::NonIntegerResult`
+    fn evaluate_integer(&self) -> Result<i64, EvalError> {
+        let integer = |v: Value| {
+            i64::try_from(v).map_err(|e| match e {
+                EvalError::NotAnInteger => EvalError::NonIntegerResult,
+                e => e,
+            })
+        };
+        let operand = |node: &Option<Box<Node>>| {
+            node.as_ref()
+                .expect("Something went wrong! (evaluate operator node with missing child)")
+                .evaluate_integer()
+        };
+
+        match &self.token {
+            Token::Value(v) => integer(*v),
+            Token::Variable(name) => match constant_value(name) {
+                Some(c) => integer(c),
+                None => Err(EvalError::UnknownVariable(name.clone())),
+            },
+            Token::Function(name) => {
+                let function = lookup(name, &FunctionRegistry::new())
+                    .ok_or_else(|| EvalError::UnknownFunction(name.clone()))?;
+                integer(function(operand(&self.right)?.into())?)
+            }
+            Token::Operator(Operator::USub) => operand(&self.right)?
+                .checked_neg()
+                .ok_or(EvalError::Overflow),
+            Token::Operator(Operator::Cond) => {
+                self.branch(operand(&self.left)? != 0).evaluate_integer()
+            }
+            Token::Operator(op) => {
+                integer(op.try_evaluate(operand(&self.left)?.into(), operand(&self.right)?.into())?)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn is_else(&self) -> bool {
+        self.token == Token::Operator(Operator::Else)
+    }
+
+    #[cfg(feature = "serde")]
+    fn is_cond(&self) -> bool {
+        self.token == Token::Operator(Operator::Cond)
+    }
+
+    /// The branch of a `Cond` node taken when its condition is `condition`
+    fn branch(&self, condition: bool) -> &Node {
+        let branches = self
+            .right
+            .as_ref()
+            .expect("Something went wrong! (conditional without branches)");
+        let branch = if condition {
+            &branches.left
+        } else {
+            &branches.right
+        };
+        branch
+            .as_ref()
+            .expect("Something went wrong! (conditional with a missing branch)")
+    }
+
+    /// Evaluate the subtree entirely in `f64`, to compare against the exact result. Functions other
+    /// than `abs` are called on the nearest `Value` to their argument
+    fn evaluate_f64(&self) -> Result<f64, EvalError> {
+        let operand = |node: &Option<Box<Node>>| {
+            node.as_ref()
+                .expect("Something went wrong! (evaluate operator node with missing child)")
+                .evaluate_f64()
+        };
+
+        Ok(match &self.token {
+            Token::Value(v) => f64::from(*v),
+            Token::Variable(name) => {
+                constant(name).ok_or_else(|| EvalError::UnknownVariable(name.clone()))?
+            }
+            Token::Function(name) if name == "abs" => operand(&self.right)?.abs(),
+            Token::Function(name) => {
+                let function = lookup(name, &FunctionRegistry::new())
+                    .ok_or_else(|| EvalError::UnknownFunction(name.clone()))?;
+                let argument =
+                    Value::checked_from_f64(operand(&self.right)?).ok_or(EvalError::Overflow)?;
+                function(argument)?.to_f64()
+            }
+            Token::Operator(Operator::USub) => -operand(&self.right)?,
+            Token::Operator(Operator::Cond) => {
+                self.branch(operand(&self.left)? != 0.0).evaluate_f64()?
+            }
             Token::Operator(op) => {
+                let (left, right) = (operand(&self.left)?, operand(&self.right)?);
                 match op {
-                    Operator::USub => -self.right.as_ref().expect("Something went wrong! (evaluate unary minus without right child)").evaluate(),
-                    _ => {
-                        op.evaluate(
-                            self.left.as_ref().expect("Something went wrong! (evaluate non unary operator node without left child").evaluate(),
-                            self.right.as_ref().expect("Something went wrong! (evaluate non unary operator node without right child").evaluate(),
-                        )
+                    Operator::Add => left + right,
+                    Operator::Sub => left - right,
+                    Operator::Mul => left * right,
+                    Operator::Div => left / right,
+                    Operator::FloorDiv => (left / right).floor(),
+                    Operator::Mod => left - right * (left / right).floor(),
+                    Operator::Pow => left.powf(right),
+                    Operator::USub | Operator::Cond | Operator::Else => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        })
+    }
+
+    /// A conservative `(min, max)` bound on the value of this subtree, given bounds for each variable
+    fn interval(
+        &self,
+        ranges: &HashMap<String, (Value, Value)>,
+    ) -> Result<(Value, Value), EvalError> {
+        let op = match &self.token {
+            Token::Value(v) => return Ok((*v, *v)),
+            Token::Variable(name) => {
+                return ranges
+                    .get(name)
+                    .copied()
+                    .or_else(|| constant_value(name).map(|c| (c, c)))
+                    .ok_or_else(|| EvalError::UnknownVariable(name.clone()))
+            }
+            Token::Function(name) if name == "abs" => {
+                let (min, max) = self
+                    .right
+                    .as_ref()
+                    .expect(
+                        "Something went wrong! (interval of function node with missing argument)",
+                    )
+                    .interval(ranges)?;
+                let zero = Value::from(0);
+                if min >= zero {
+                    return Ok((min, max));
+                }
+                let neg_min = min.checked_neg().ok_or(EvalError::Overflow)?;
+                return Ok(if max <= zero {
+                    (max.checked_neg().ok_or(EvalError::Overflow)?, neg_min)
+                } else {
+                    (zero, if neg_min > max { neg_min } else { max })
+                });
+            }
+            Token::Function(name) => return Err(EvalError::UnknownFunction(name.clone())),
+            Token::Operator(op) => *op,
+            _ => unreachable!(),
+        };
+        let operand = |node: &Option<Box<Node>>| {
+            node.as_ref()
+                .expect("Something went wrong! (interval of operator node with missing child)")
+                .interval(ranges)
+        };
+        let checked = |v: Option<Value>| v.ok_or(EvalError::Overflow);
+
+        if op == Operator::Cond {
+            let zero = Value::from(0);
+            let (condition_min, condition_max) = operand(&self.left)?;
+            if condition_min > zero || condition_max < zero {
+                return self.branch(true).interval(ranges);
+            } else if condition_min == zero && condition_max == zero {
+                return self.branch(false).interval(ranges);
+            }
+
+            // either branch could be taken
+            let (true_min, true_max) = self.branch(true).interval(ranges)?;
+            let (false_min, false_max) = self.branch(false).interval(ranges)?;
+            let min = if true_min < false_min {
+                true_min
+            } else {
+                false_min
+            };
+            let max = if true_max > false_max {
+                true_max
+            } else {
+                false_max
+            };
+            return Ok((min, max));
+        }
+
+        let (right_min, right_max) = operand(&self.right)?;
+        if op == Operator::USub {
+            return Ok((
+                checked(right_max.checked_neg())?,
+                checked(right_min.checked_neg())?,
+            ));
+        }
+        let (left_min, left_max) = operand(&self.left)?;
+
+        match op {
+            Operator::Add => Ok((
+                checked(left_min.checked_add(right_min))?,
+                checked(left_max.checked_add(right_max))?,
+            )),
+            Operator::Sub => Ok((
+                checked(left_min.checked_sub(right_max))?,
+                checked(left_max.checked_sub(right_min))?,
+            )),
+            Operator::Mul | Operator::Div => {
+                let (right_min, right_max) = if op == Operator::Div {
+                    // dividing is multiplying by the reciprocal, which flips the bounds
+                    if right_min <= Value::from(0) && right_max >= Value::from(0) {
+                        return Err(EvalError::DivideByZero);
+                    }
+                    let one = Value::from(1);
+                    (
+                        checked(one.checked_div(right_max))?,
+                        checked(one.checked_div(right_min))?,
+                    )
+                } else {
+                    (right_min, right_max)
+                };
+
+                let mut products = Vec::new();
+                for &l in &[left_min, left_max] {
+                    for &r in &[right_min, right_max] {
+                        products.push(checked(l.checked_mul(r))?);
                     }
                 }
+                let min = products
+                    .iter()
+                    .fold(products[0], |min, &p| if p < min { p } else { min });
+                let max = products
+                    .iter()
+                    .fold(products[0], |max, &p| if p > max { p } else { max });
+                Ok((min, max))
+            }
+            // the other operators only have exact values
+            _ if left_min == left_max && right_min == right_max => {
+                let v = op.try_evaluate_with_mode(left_min, right_min, EvalMode::default())?;
+                Ok((v, v))
+            }
+            _ => Err(EvalError::OutOfDomain),
+        }
+    }
+
+    /// Fold constant subtrees into values, and drop operations that have no effect (`x*1`, `x+0`, ...)
+    fn simplify(&self) -> Node {
+        let left = self.left.as_ref().map(|node| node.simplify());
+        let right = self.right.as_ref().map(|node| node.simplify());
+
+        let op = match self.token {
+            Token::Operator(op) => op,
+            Token::Function(_) => {
+                return Node {
+                    token: self.token.clone(),
+                    left: None,
+                    right: right.map(Box::new),
+                }
+            }
+            _ => return self.clone(),
+        };
+        let value_of = |node: &Option<Node>| match node.as_ref().map(|node| &node.token) {
+            Some(Token::Value(v)) => Some(*v),
+            _ => None,
+        };
+
+        match (op, value_of(&left), value_of(&right)) {
+            (Operator::Cond, Some(condition), _) => self.branch(condition != 0).simplify(),
+            // the branches of a conditional aren't combined
+            (Operator::Else, _, _) => Node {
+                token: self.token.clone(),
+                left: left.map(Box::new),
+                right: right.map(Box::new),
+            },
+            (Operator::USub, _, Some(r)) => match r.checked_neg() {
+                Some(v) => Node::new(Token::Value(v)),
+                None => Node {
+                    token: self.token.clone(),
+                    left: None,
+                    right: right.map(Box::new),
+                },
+            },
+            (_, Some(l), Some(r)) => match op.try_evaluate(l, r) {
+                Ok(v) => Node::new(Token::Value(v)),
+                // left in place for evaluating to report
+                Err(_) => Node {
+                    token: self.token.clone(),
+                    left: left.map(Box::new),
+                    right: right.map(Box::new),
+                },
+            },
+            (Operator::Add, _, Some(r)) | (Operator::Sub, _, Some(r)) if r == 0 => left.unwrap(),
+            (Operator::Add, Some(l), _) if l == 0 => right.unwrap(),
+            (Operator::Mul, _, Some(r))
+            | (Operator::Div, _, Some(r))
+            | (Operator::Pow, _, Some(r))
+                if r == 1 =>
+            {
+                left.unwrap()
+            }
+            (Operator::Mul, Some(l), _) if l == 1 => right.unwrap(),
+            // not when the other operand holds a fold that failed, erasing it would hide the error
+            (Operator::Mul, Some(z), _) | (Operator::Mul, _, Some(z))
+                if z == 0 && left.iter().chain(right.iter()).all(Node::is_erasable) =>
+            {
+                Node::new(Token::Value(z))
+            }
+            _ => Node {
+                token: self.token.clone(),
+                left: left.map(Box::new),
+                right: right.map(Box::new),
             },
-            _ => unreachable!()
         }
     }
 
-    #[cfg(test)]
-    fn depth(&self) -> u16 {
-        let l = match self.left.as_ref() {
-            Some(node) => node.depth(),
-            None => 0,
+    /// Whether dropping this simplified subtree can't hide an error from evaluating it
+    fn is_erasable(&self) -> bool {
+        self.erasable_has_variable().is_some()
+    }
+
+    /// Whether the subtree has a variable, `None` if it holds a function call (which might fail on
+    /// any argument) or a constant operator node, which is only left after simplifying when it
+    /// failed to fold
+    fn erasable_has_variable(&self) -> Option<bool> {
+        match self.token {
+            Token::Value(_) => Some(false),
+            Token::Variable(_) => Some(true),
+            Token::Function(_) => None,
+            _ => {
+                let mut has_variable = false;
+                for child in self.left.iter().chain(self.right.iter()) {
+                    has_variable |= child.erasable_has_variable()?;
+                }
+                has_variable.then_some(true)
+            }
+        }
+    }
+
+    /// `{"op": "+", "left": .., "right": ..}`, leaves are `{"value": "7/2"}` or `{"variable": "x"}` and
+    /// calls are `{"function": "abs", "right": ..}`. Missing children are left out
+    #[cfg(feature = "serde")]
+    fn to_json(&self) -> serde_json::Value {
+        let mut object = serde_json::Map::new();
+        let (key, name) = match &self.token {
+            Token::Value(v) => ("value", v.format(DisplayStyle::Fraction)),
+            Token::Variable(name) => ("variable", name.clone()),
+            Token::Function(name) => ("function", name.clone()),
+            Token::Operator(op) => ("op", op.to_str().to_string()),
+            _ => unreachable!(),
         };
-        let r = match self.right.as_ref() {
-            Some(node) => node.depth(),
-            None => 0,
+        object.insert(key.to_string(), name.into());
+        for (key, child) in [("left", &self.left), ("right", &self.right)].iter() {
+            if let Some(child) = child {
+                object.insert(key.to_string(), child.to_json());
+            }
+        }
+        serde_json::Value::Object(object)
+    }
+
+    /// The inverse of `to_json`, checking that every node has the children its token needs
+    #[cfg(feature = "serde")]
+    fn from_json(json: &serde_json::Value) -> Result<Node, EvalError> {
+        let invalid = |reason: String| EvalError::InvalidJson(reason);
+        let object = json
+            .as_object()
+            .ok_or_else(|| invalid(format!("expected a node object, found {}", json)))?;
+        let string = |key: &str| {
+            object
+                .get(key)
+                .map(|field| {
+                    field
+                        .as_str()
+                        .ok_or_else(|| invalid(format!("{:?} should be a string", key)))
+                })
+                .transpose()
+        };
+        let child = |key: &str| {
+            object
+                .get(key)
+                .map(|child| Node::from_json(child).map(Box::new))
+                .transpose()
+        };
+
+        let token = if let Some(value) = string("value")? {
+            Token::Value(match value {
+                "inf" => Value::Infinity,
+                "-inf" => Value::NegInfinity,
+                "undefined" => Value::Undefined,
+                _ => Value::parse_literal(value)?,
+            })
+        } else if let Some(symbol) = string("op")? {
+            let chars: Vec<char> = symbol.chars().collect();
+            let op = match chars[..] {
+                [c] => Operator::from_char(c),
+                [first, second] => Operator::from_chars(first, second),
+                _ => None,
+            };
+            Token::Operator(
+                op.filter(|op| op.to_str() == symbol)
+                    .ok_or_else(|| invalid(format!("unknown operator {:?}", symbol)))?,
+            )
+        } else if let Some(name) = string("variable")? {
+            Token::Variable(name.to_string())
+        } else if let Some(name) = string("function")? {
+            Token::Function(name.to_string())
+        } else {
+            return Err(invalid(format!(
+                "expected one of \"op\", \"value\", \"variable\" or \"function\" in {}",
+                json
+            )));
+        };
+        let node = Node {
+            token,
+            left: child("left")?,
+            right: child("right")?,
         };
 
-        l.max(r) + 1
+        // an `Else` only ever holds the branches of the `Cond` directly above it
+        let is_else =
+            |child: &Option<Box<Node>>| child.as_ref().is_some_and(|child| child.is_else());
+        let valid = match &node.token {
+            Token::Operator(Operator::USub) | Token::Function(_) => {
+                node.left.is_none() && node.right.is_some()
+            }
+            Token::Operator(Operator::Cond) => node.left.is_some() && is_else(&node.right),
+            Token::Operator(_) => node.left.is_some() && node.right.is_some(),
+            _ => node.left.is_none() && node.right.is_none(),
+        };
+        if !valid || is_else(&node.left) || (!node.is_cond() && is_else(&node.right)) {
+            return Err(invalid(format!(
+                "{:?} has the wrong operands in {}",
+                node.token, json
+            )));
+        }
+        Ok(node)
+    }
+
+    /// How tightly this node binds for the sake of grouping, negative values bind like unary minus
+    fn binding(&self) -> u32 {
+        match &self.token {
+            Token::Operator(_) => precedence(&self.token),
+            Token::Value(v) if v.to_latex().starts_with('-') => {
+                precedence(&Token::Operator(Operator::USub))
+            }
+            _ => u32::MAX,
+        }
+    }
+
+    fn to_latex(&self) -> String {
+        let op = match &self.token {
+            Token::Value(v) => return v.to_latex(),
+            Token::Variable(name) => return name.clone(),
+            Token::Function(name) => {
+                let argument = self
+                    .right
+                    .as_ref()
+                    .expect("Something went wrong! (format function without argument)");
+                return format!(
+                    "\\operatorname{{{}}}\\left({}\\right)",
+                    name,
+                    argument.to_latex()
+                );
+            }
+            Token::Operator(op) => *op,
+            _ => unreachable!(),
+        };
+        let p = precedence(&self.token);
+        let negation = precedence(&Token::Operator(Operator::USub));
+
+        // wrap `node` in parens when it binds looser than `threshold`
+        let group = |node: &Option<Box<Node>>, threshold: u32| {
+            let node = node
+                .as_ref()
+                .expect("Something went wrong! (format operator node with missing child)");
+            if node.binding() < threshold {
+                format!("\\left({}\\right)", node.to_latex())
+            } else {
+                node.to_latex()
+            }
+        };
+        // right hand operands of non-associative operators also group at equal precedence
+        // (`a - (b - c)`), and negative right hand operands always group (`a * (-b)`)
+        let group_right = |node: &Option<Box<Node>>, associative: bool| {
+            let binding = node.as_ref().map(|node| node.binding()).unwrap_or(u32::MAX);
+            if binding == negation || (!associative && binding == p) {
+                group(node, u32::MAX)
+            } else {
+                group(node, p)
+            }
+        };
+
+        match op {
+            Operator::Add => format!(
+                "{} + {}",
+                group(&self.left, p),
+                group_right(&self.right, true)
+            ),
+            Operator::Sub => format!(
+                "{} - {}",
+                group(&self.left, p),
+                group_right(&self.right, false)
+            ),
+            Operator::Mul => format!(
+                "{} \\cdot {}",
+                group(&self.left, p),
+                group_right(&self.right, true)
+            ),
+            Operator::Mod => format!(
+                "{} \\bmod {}",
+                group(&self.left, p),
+                group_right(&self.right, false)
+            ),
+            Operator::Div => format!(
+                "\\frac{{{}}}{{{}}}",
+                group(&self.left, 0),
+                group(&self.right, 0)
+            ),
+            Operator::FloorDiv => format!(
+                "\\left\\lfloor \\frac{{{}}}{{{}}} \\right\\rfloor",
+                group(&self.left, 0),
+                group(&self.right, 0)
+            ),
+            Operator::Pow => format!(
+                "{}^{{{}}}",
+                group(&self.left, u32::MAX),
+                group(&self.right, 0)
+            ),
+            Operator::USub => format!("-{}", group(&self.right, p - 1)),
+            Operator::Cond => format!(
+                "{} \\mathbin{{?}} {}",
+                group(&self.left, p + 1),
+                group(&self.right, p)
+            ),
+            Operator::Else => format!("{} : {}", group(&self.left, p), group(&self.right, p)),
+        }
+    }
+
+    /// Feed the tokens of this subtree into the FNV-1a `hash` in post-order, each as a tag byte,
+    /// its text and a 0 byte. Values are written as reduced improper fractions, so the encoding
+    /// doesn't depend on the configured `DisplayStyle`
+    fn fingerprint_postorder(&self, hash: &mut u64) {
+        if let Some(left) = self.left.as_ref() {
+            left.fingerprint_postorder(hash);
+        }
+        if let Some(right) = self.right.as_ref() {
+            right.fingerprint_postorder(hash);
+        }
+        let (tag, text) = match &self.token {
+            Token::Operator(op) => (b'o', op.to_str().to_string()),
+            Token::Value(v) => (b'v', v.to_improper_string()),
+            Token::Paren(p) => (b'p', p.to_char().to_string()),
+            Token::Variable(name) => (b'x', name.clone()),
+            Token::Function(name) => (b'f', name.clone()),
+        };
+        for &byte in [tag].iter().chain(text.as_bytes()).chain(&[0]) {
+            *hash = (*hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    /// Push the tokens of this subtree to `tokens` in postfix order
+    fn postorder(&self, tokens: &mut Vec<Token>) {
+        if let Some(left) = self.left.as_ref() {
+            left.postorder(tokens);
+        }
+        if let Some(right) = self.right.as_ref() {
+            right.postorder(tokens);
+        }
+        tokens.push(self.token.clone());
+    }
+
+    /// Count each operator in this subtree into `counts`
+    fn count_operators(&self, counts: &mut HashMap<Operator, usize>) {
+        if let Token::Operator(op) = self.token {
+            *counts.entry(op).or_insert(0) += 1;
+        }
+        if let Some(left) = self.left.as_ref() {
+            left.count_operators(counts);
+        }
+        if let Some(right) = self.right.as_ref() {
+            right.count_operators(counts);
+        }
+    }
+
+    /// Whether a division in this subtree evaluates to something other than an integer
+    fn has_nonexact_division(&self) -> bool {
+        let children = [self.left.as_ref(), self.right.as_ref()];
+        if children
+            .iter()
+            .flatten()
+            .any(|child| child.has_nonexact_division())
+        {
+            return true;
+        }
+
+        self.token == Token::Operator(Operator::Div)
+            && matches!(
+                self.evaluate(
+                    &HashMap::new(),
+                    &FunctionRegistry::new(),
+                    EvalMode::default()
+                ),
+                Ok(Value::Rational { .. })
+            )
+    }
+
+    /// The number of nodes in this subtree
+    fn size(&self) -> usize {
+        // on a stack rather than recursively, `evaluate_with_limits` measures trees of any depth
+        let mut size = 0;
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            size += 1;
+            stack.extend(node.left.as_deref());
+            stack.extend(node.right.as_deref());
+        }
+        size
+    }
+
+    /// The number of operators and function calls in this subtree, the `Else` of a conditional is
+    /// part of its `Cond`
+    fn operations(&self) -> usize {
+        let own = match self.token {
+            Token::Operator(Operator::Else) => 0,
+            Token::Operator(_) | Token::Function(_) => 1,
+            _ => 0,
+        };
+        let children = [self.left.as_ref(), self.right.as_ref()];
+        own + children
+            .iter()
+            .flatten()
+            .map(|child| child.operations())
+            .sum::<usize>()
+    }
+
+    fn depth(&self) -> usize {
+        let mut depth = 0;
+        let mut stack = vec![(self, 1)];
+        while let Some((node, node_depth)) = stack.pop() {
+            depth = depth.max(node_depth);
+            stack.extend(node.left.as_deref().map(|left| (left, node_depth + 1)));
+            stack.extend(node.right.as_deref().map(|right| (right, node_depth + 1)));
+        }
+        depth
+    }
+}
+impl Drop for Node {
+    /// Take the children apart on a stack, the default drop recurses once per level
+    fn drop(&mut self) {
+        let mut stack: Vec<Box<Node>> = self
+            .left
+            .take()
+            .into_iter()
+            .chain(self.right.take())
+            .collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
     }
 }
 impl From<Token> for Node {
@@ -59,8 +801,17 @@ impl From<Token> for Node {
 }
 impl Debug for Node {
     fn fmt(&self, f: &mut Formatter) -> fmt_Result {
-        match self.token {
+        match &self.token {
             Token::Value(v) => write!(f, "{}", v),
+            Token::Variable(name) => write!(f, "{}", name),
+            Token::Function(name) => write!(
+                f,
+                "{}({:?})",
+                name,
+                self.right
+                    .as_ref()
+                    .expect("Something went wrong! (format function without argument)")
+            ),
             Token::Operator(op) => match op {
                 Operator::USub => write!(
                     f,
@@ -76,7 +827,7 @@ impl Debug for Node {
                         self.left.as_ref().expect(
                             "Something went wrong! (format operator node with no left child)"
                         ),
-                        op.to_string(),
+                        op,
                         self.right.as_ref().expect(
                             "Something went wrong! (format operator node with no right child)"
                         ),
@@ -88,50 +839,596 @@ impl Debug for Node {
     }
 }
 
+/// Options controlling how a tree is evaluated
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EvalMode {
+    pub modulo: Modulo,
+    /// Divide by zero gives `Value::Infinity` or `Value::NegInfinity` (`Value::Undefined` for `0/0`)
+    /// instead of an error, and those propagate like IEEE floats
+    pub non_finite: bool,
+    /// Approximate every result with a denominator above this, see `Config::max_denominator`
+    pub max_denominator: Option<i64>,
+}
+impl EvalMode {
+    /// The default mode with the `max_denominator` of the current `Config`, what the `Tree`
+    /// methods without a mode evaluate in
+    pub fn from_config() -> Self {
+        EvalMode {
+            max_denominator: config().max_denominator,
+            ..EvalMode::default()
+        }
+    }
+}
+
+/// The value of an expression along with a summary of how it was computed, from
+/// `Tree::evaluate_detailed`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EvalResult {
+    pub value: Value,
+    /// The operators and function calls in the expression, including both branches of a conditional
+    pub operations: usize,
+    /// Whether any `/` that was evaluated produced a fraction, like `Tree::has_nonexact_division`
+    /// but only for the branches taken. After a float fallback, only the divisions before the overflow
+    pub inexact_division: bool,
+    /// Whether an intermediate result overflowed and `value` is the `f64` fallback of
+    /// `Tree::evaluate_lossy`, so it may not be exact
+    pub float_fallback: bool,
+}
+
+/// A single-argument function that expressions can call by name, `double(21)`
+pub type Function = fn(Value) -> Result<Value, EvalError>;
+
+/// The functions expressions can call, by name
+pub type FunctionRegistry = HashMap<String, Function>;
+
+/// Functions every expression can call, a `FunctionRegistry` entry of the same name is used instead.
+/// `abs` is what `|x|` is read as
+pub const BUILTIN_FUNCTIONS: &[(&str, Function)] =
+    &[("abs", |v| v.checked_abs().ok_or(EvalError::Overflow))];
+
+/// Constants every expression can use by name, a variable bound to the same name is used instead.
+/// Exact evaluation uses the rational `From<f64>` gives for each, limited to a denominator of
+/// `CONSTANT_MAX_DENOMINATOR` so that products and powers of them still fit
+pub const CONSTANTS: &[(&str, f64)] = &[
+    ("pi", std::f64::consts::PI),
+    ("e", std::f64::consts::E),
+    ("tau", std::f64::consts::TAU),
+    // (1 + sqrt(5)) / 2
+    ("phi", 1.618_033_988_749_895),
+];
+
+/// The `f64` rationals of `CONSTANTS` have denominators around `10^15`, this keeps them within
+/// about `1e-12` of the float
+pub const CONSTANT_MAX_DENOMINATOR: i64 = 1_000_000;
+
+/// The float value of the constant called `name`
+fn constant(name: &str) -> Option<f64> {
+    CONSTANTS
+        .iter()
+        .find(|&&(constant, _)| constant == name)
+        .map(|&(_, value)| value)
+}
+
+/// The rational the constant called `name` is evaluated as
+fn constant_value(name: &str) -> Option<Value> {
+    constant(name).map(|c| Value::from(c).limit_denominator(CONSTANT_MAX_DENOMINATOR))
+}
+
+/// The function called `name`, from `functions` or else the builtins
+fn lookup(name: &str, functions: &FunctionRegistry) -> Option<Function> {
+    functions.get(name).copied().or_else(|| {
+        BUILTIN_FUNCTIONS
+            .iter()
+            .find(|&&(builtin, _)| builtin == name)
+            .map(|&(_, function)| function)
+    })
+}
+
+/// Options controlling how an expression is parsed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ParseOptions {
+    /// Read adjacent operands as a product, so `2(3)` is `2*(3)`. Digits in parens after a decimal
+    /// are a product too, `1.5(3)` is `1.5*3` rather than the repeating decimal `1.5333...`
+    pub implicit_mul: bool,
+    /// Scale numbers suffixed with a unit from `UNITS`, so `3k` is `3000`
+    pub units: bool,
+    /// Error on operands with no operator between them (`2 3`, `(1)(2)`), this takes precedence
+    /// over `implicit_mul`
+    pub require_operators: bool,
+    /// Error on decimal literals, so numbers have to be written as integers and fractions (`1/2`
+    /// rather than `0.5`)
+    pub fractions_only: bool,
+    /// How numbers are written, `3,5` rather than `3.5` with a decimal comma
+    pub locale: LocaleOptions,
+}
+
 #[derive(Clone)]
 pub struct Tree {
     root: Node,
 }
 impl Tree {
     pub fn new(s: &str) -> Self {
-        shunting_yard(tokenize(s)).into()
+        Self::try_new(s).unwrap_or_else(|e| panic!("{}", e))
     }
 
-    pub fn evaluate(&self) -> Value {
-        self.root.evaluate()
+    pub fn try_new(s: &str) -> Result<Self, EvalError> {
+        Self::try_new_with_options(s, ParseOptions::default())
     }
-}
-impl From<Vec<Token>> for Tree {
-    fn from(stream: Vec<Token>) -> Self {
+
+    pub fn new_with_options(s: &str, options: ParseOptions) -> Self {
+        Self::try_new_with_options(s, options).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_new_with_options(s: &str, options: ParseOptions) -> Result<Self, EvalError> {
+        // input straight from a line reader keeps its newline
+        let s = s.trim();
+        // a decimal point can only ever be part of a number
+        if let Some(pos) = s
+            .find(options.locale.decimal_point())
+            .filter(|_| options.fractions_only)
+        {
+            return Err(LexError::DecimalNotAllowed(pos).into());
+        }
+        let mut tokens = if options.require_operators {
+            tokenize_explicit_with_locale(s, options.locale)?
+        } else if options.implicit_mul {
+            tokenize_implicit_with_locale(s, options.locale)?
+        } else {
+            tokenize_with_locale(s, options.locale)?
+        };
+        if options.units {
+            tokens = apply_units(tokens)?;
+        }
+        if options.implicit_mul {
+            tokens = insert_implicit_mul(tokens);
+        }
+        Self::try_from_tokens(shunting_yard(tokens))
+    }
+
+    /// Build a tree from a postfix token stream, reporting malformed streams rather than panicking
+    pub fn try_from_tokens(stream: Vec<Token>) -> Result<Self, EvalError> {
         let mut stack: Vec<Node> = Vec::new();
 
         for token in stream {
-            match token {
-                Token::Value(_) => stack.push(token.into()),
-                Token::Operator(op) => match op {
-                    Operator::USub => {
-                        let mut node: Node = token.clone().into();
-                        let value = stack.pop().expect("Unable to pop from empty stack");
-                        node.right = Some(Box::new(value));
-                        stack.push(node);
+            let node = match token {
+                Token::Value(_) | Token::Variable(_) => token.into(),
+                Token::Operator(Operator::USub) | Token::Function(_) => {
+                    let value = stack.pop().ok_or(EvalError::MissingOperand)?;
+                    if value.is_else() {
+                        return Err(EvalError::InvalidConditional);
                     }
-                    _ => {
-                        let mut node: Node = token.clone().into();
-                        let a: Node = stack.pop().expect("Stack shouldn't be empty? :(");
-                        let b: Node = stack.pop().expect("Stack shouldn't be empty? :(");
-                        node.right = Some(Box::new(a));
-                        node.left = Some(Box::new(b));
-                        stack.push(node);
+                    let mut node: Node = token.into();
+                    node.right = Some(Box::new(value));
+                    node
+                }
+                Token::Operator(op) => {
+                    let a = stack.pop().ok_or(EvalError::MissingOperand)?;
+                    let b = stack.pop().ok_or(EvalError::MissingOperand)?;
+                    // a `:` is only valid as the right operand of a `?`, and every `?` needs one
+                    if b.is_else() || a.is_else() != (op == Operator::Cond) {
+                        return Err(EvalError::InvalidConditional);
                     }
-                },
-                _ => unreachable!(),
+                    let mut node: Node = token.into();
+                    node.right = Some(Box::new(a));
+                    node.left = Some(Box::new(b));
+                    node
+                }
+                // an unclosed left paren makes it through the shunting yard
+                Token::Paren(_) => return Err(EvalError::UnbalancedParens),
+            };
+            stack.push(node);
+        }
+
+        match (stack.pop(), stack.is_empty()) {
+            (Some(root), true) if root.is_else() => Err(EvalError::InvalidConditional),
+            (Some(root), true) => Ok(Tree { root }),
+            (Some(_), false) => Err(EvalError::MissingOperator),
+            (None, _) => Err(EvalError::EmptyExpression),
+        }
+    }
+
+    pub fn evaluate(&self) -> Value {
+        self.evaluate_with_mode(EvalMode::from_config())
+    }
+
+    pub fn evaluate_with_mode(&self, mode: EvalMode) -> Value {
+        self.root
+            .evaluate(&HashMap::new(), &FunctionRegistry::new(), mode)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Evaluate the tree, erroring with `EvalError::Timeout` if it takes longer than `budget`
+    pub fn evaluate_timeout(&self, budget: Duration) -> Result<Value, EvalError> {
+        self.root.evaluate_before(
+            &HashMap::new(),
+            &FunctionRegistry::new(),
+            EvalMode::from_config(),
+            Some(Instant::now() + budget),
+        )
+    }
+
+    /// Evaluate the tree, taking the value of each variable from `vars`
+    pub fn evaluate_with(&self, vars: &HashMap<String, Value>) -> Result<Value, EvalError> {
+        self.root
+            .evaluate(vars, &FunctionRegistry::new(), EvalMode::from_config())
+    }
+
+    /// Evaluate the tree once for each of `values` bound to `var`, for tabulating a function. The
+    /// first error stops the evaluation
+    pub fn evaluate_over(&self, var: char, values: &[Value]) -> Result<Vec<Value>, EvalError> {
+        let mut vars = HashMap::new();
+        values
+            .iter()
+            .map(|&value| {
+                vars.insert(var.to_string(), value);
+                self.evaluate_with(&vars)
+            })
+            .collect()
+    }
+
+    /// Evaluate the tree, calling each function by looking its name up in `functions`
+    pub fn evaluate_with_functions(
+        &self,
+        functions: &FunctionRegistry,
+    ) -> Result<Value, EvalError> {
+        self.root
+            .evaluate(&HashMap::new(), functions, EvalMode::from_config())
+    }
+
+    /// Evaluate the postfix form of the expression on a stack, recording the stack after each token
+    ///
+    /// An error ends the trace with its message
+    pub fn evaluate_debug(&self) -> Vec<String> {
+        let mut tokens = Vec::new();
+        self.root.postorder(&mut tokens);
+
+        let mode = EvalMode::from_config();
+        let mut stack: Vec<Value> = Vec::new();
+        let mut states = Vec::new();
+        for token in tokens {
+            let result = match token {
+                Token::Value(v) => Ok(v),
+                Token::Variable(name) => {
+                    constant_value(&name).ok_or(EvalError::UnknownVariable(name))
+                }
+                Token::Function(name) => {
+                    let argument = stack
+                        .pop()
+                        .expect("Something went wrong! (function without argument)");
+                    lookup(&name, &FunctionRegistry::new())
+                        .ok_or(EvalError::UnknownFunction(name))
+                        .and_then(|function| function(argument))
+                }
+                Token::Operator(Operator::USub) => {
+                    let right = stack
+                        .pop()
+                        .expect("Something went wrong! (unary minus without operand)");
+                    right.checked_neg().ok_or(EvalError::Overflow)
+                }
+                // both branches stay on the stack for the `?` to choose between
+                Token::Operator(Operator::Else) => Ok(stack
+                    .pop()
+                    .expect("Something went wrong! (branches without false branch)")),
+                Token::Operator(Operator::Cond) => {
+                    let on_false = stack
+                        .pop()
+                        .expect("Something went wrong! (conditional without false branch)");
+                    let on_true = stack
+                        .pop()
+                        .expect("Something went wrong! (conditional without true branch)");
+                    let condition = stack
+                        .pop()
+                        .expect("Something went wrong! (conditional without condition)");
+                    Ok(if condition != 0 { on_true } else { on_false })
+                }
+                Token::Operator(op) => {
+                    let right = stack
+                        .pop()
+                        .expect("Something went wrong! (operator without right operand)");
+                    let left = stack
+                        .pop()
+                        .expect("Something went wrong! (operator without left operand)");
+                    op.try_evaluate_with_mode(left, right, mode)
+                }
+                Token::Paren(_) => unreachable!(),
+            };
+
+            match result {
+                Ok(v) => stack.push(v),
+                Err(e) => {
+                    states.push(format!("Error: {}", e));
+                    break;
+                }
             }
+            let contents: Vec<String> = stack.iter().map(|v| v.to_string()).collect();
+            states.push(format!("[{}]", contents.join(", ")));
         }
 
+        states
+    }
+
+    /// The exact value alongside its absolute difference from the same expression evaluated in `f64`
+    pub fn evaluate_with_error(&self) -> (Value, f64) {
+        let exact = self.evaluate();
+        let float = self.root.evaluate_f64().unwrap_or_else(|e| panic!("{}", e));
+        (exact, (f64::from(exact) - float).abs())
+    }
+
+    /// Evaluate the tree exactly, or in `f64` if an intermediate result overflows, flagging whether
+    /// the value came from the float fallback and may not be exact. Any other error, or a float
+    /// result that doesn't fit in a `Value` either, is returned as it is
+    pub fn evaluate_lossy(&self) -> Result<(Value, bool), EvalError> {
+        self.evaluate_lossy_noting(&mut false)
+    }
+
+    /// `evaluate_lossy`, noting inexact divisions of the exact evaluation like `Node::evaluate_noting`
+    fn evaluate_lossy_noting(
+        &self,
+        inexact_division: &mut bool,
+    ) -> Result<(Value, bool), EvalError> {
+        let exact = self.root.evaluate_noting(
+            &HashMap::new(),
+            &FunctionRegistry::new(),
+            EvalMode::from_config(),
+            None,
+            inexact_division,
+        );
+        match exact {
+            Ok(exact) => Ok((exact, false)),
+            Err(EvalError::Overflow) => {
+                let approximate = Value::checked_from_f64(self.root.evaluate_f64()?)
+                    .ok_or(EvalError::Overflow)?;
+                Ok((approximate, true))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Evaluate the tree in integers throughout, erroring with `EvalError::NonIntegerResult` as soon
+    /// as a division leaves a remainder or any other step isn't whole, even if a later step would
+    /// make it whole again (`7/2*2`)
+    pub fn evaluate_integer(&self) -> Result<i64, EvalError> {
+        self.root.evaluate_integer()
+    }
+
+    /// The running value after each operation of a chain of one left-associative operator at the
+    /// root, `1+2+3+4` gives `[1, 3, 6, 10]`. Any other root is a chain of one, its value. The
+    /// first error in an operand or a step is returned instead
+    pub fn partial_results(&self) -> Result<Vec<Value>, EvalError> {
+        let mode = EvalMode::from_config();
+        let evaluate = |node: &Node| node.evaluate(&HashMap::new(), &FunctionRegistry::new(), mode);
+        let chained = match self.root.token {
+            Token::Operator(op)
+                if OperatorAssociativity::from(&self.root.token) == OperatorAssociativity::Left =>
+            {
+                Some(op)
+            }
+            _ => None,
+        };
+
+        // walk down the left spine to the first operand, collecting the right hand operands
+        let mut first = &self.root;
+        let mut rest = Vec::new();
+        while let Some(op) = chained.filter(|&op| first.token == Token::Operator(op)) {
+            let missing = "Something went wrong! (operator node with missing child)";
+            rest.push((op, first.right.as_deref().expect(missing)));
+            first = first.left.as_deref().expect(missing);
+        }
+
+        let mut results = vec![evaluate(first)?];
+        for (op, operand) in rest.into_iter().rev() {
+            let partial = results[results.len() - 1];
+            results.push(op.try_evaluate(partial, evaluate(operand)?)?);
+        }
+        Ok(results)
+    }
+
+    /// `evaluate_lossy` along with what the expression did to get there
+    pub fn evaluate_detailed(&self) -> Result<EvalResult, EvalError> {
+        let mut inexact_division = false;
+        let (value, float_fallback) = self.evaluate_lossy_noting(&mut inexact_division)?;
+        Ok(EvalResult {
+            value,
+            operations: self.root.operations(),
+            inexact_division,
+            float_fallback,
+        })
+    }
+
+    /// The `(min, max)` the expression can take, an expression without variables gives its exact value
+    pub fn interval(&self) -> Result<(Value, Value), EvalError> {
+        self.interval_with(&HashMap::new())
+    }
+
+    /// A conservative `(min, max)` bound on the expression when each variable lies within its
+    /// `(min, max)` in `ranges`, using interval arithmetic over `+`, `-`, `*` and `/`
+    pub fn interval_with(
+        &self,
+        ranges: &HashMap<String, (Value, Value)>,
+    ) -> Result<(Value, Value), EvalError> {
+        self.root.interval(ranges)
+    }
+
+    /// Fold every constant subtree into a single value and apply identities like `x*1 -> x`,
+    /// an expression without variables simplifies to a single value
+    pub fn simplify(&self) -> Tree {
         Tree {
-            root: stack.pop().expect("Empty string? maybe? (stack empty)"),
+            root: self.root.simplify(),
         }
     }
+
+    /// Render the expression as LaTeX, using `\frac` for division and `\cdot` for multiplication
+    pub fn to_latex(&self) -> String {
+        self.root.to_latex()
+    }
+
+    /// The structure of the tree as nested JSON objects, see `from_json` to read it back
+    /// ```rust
+    /// # use rational_calculator::tree::Tree;
+    /// assert_eq!(
+    ///     Tree::new("x - 3").to_json(),
+    ///     r#"{"left":{"variable":"x"},"op":"-","right":{"value":"3"}}"#
+    /// );
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        self.root.to_json().to_string()
+    }
+
+    /// Read back a tree written by `to_json`
+    #[cfg(feature = "serde")]
+    pub fn from_json(s: &str) -> Result<Self, EvalError> {
+        let json: serde_json::Value =
+            serde_json::from_str(s).map_err(|e| EvalError::InvalidJson(e.to_string()))?;
+        let root = Node::from_json(&json)?;
+        if root.is_else() {
+            return Err(EvalError::InvalidJson(
+                "\":\" outside of a conditional".to_string(),
+            ));
+        }
+        Ok(Tree { root })
+    }
+
+    /// Hash the structure of the tree, structurally identical trees share a fingerprint
+    /// (`1+2` and `2+1` do not). It's the 64-bit FNV-1a of the post-order tokens, so it's the same
+    /// across builds and platforms and can be stored
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325;
+        self.root.fingerprint_postorder(&mut hash);
+        hash
+    }
+
+    /// Count how many times each operator appears in the tree
+    pub fn operation_histogram(&self) -> HashMap<Operator, usize> {
+        let mut counts = HashMap::new();
+        self.root.count_operators(&mut counts);
+        counts
+    }
+
+    /// Whether any `/` in the tree produces a fraction, `7/4` does but `8/4` doesn't. Divisions
+    /// that can't be evaluated (unknown variables, division by zero) don't count
+    pub fn has_nonexact_division(&self) -> bool {
+        self.root.has_nonexact_division()
+    }
+}
+
+/// Split a trailing `where name = value, ...` clause off of `s`, returning the expression
+/// and the bound variables. Each value is evaluated with the bindings before it in scope
+pub fn parse_where(s: &str) -> Result<(&str, HashMap<String, Value>), EvalError> {
+    let mut vars = HashMap::new();
+
+    let is_word_boundary = |idx: usize| {
+        !s[..idx].ends_with(|c: char| c.is_ascii_alphabetic())
+            && !s[idx + "where".len()..].starts_with(|c: char| c.is_ascii_alphabetic())
+    };
+    let clause = match s
+        .match_indices("where")
+        .find(|&(idx, _)| is_word_boundary(idx))
+    {
+        Some((idx, _)) => idx,
+        None => return Ok((s, vars)),
+    };
+
+    for binding in s[clause + "where".len()..].split(',') {
+        let invalid = || EvalError::InvalidBinding(binding.trim().to_string());
+        let (name, value) = binding.split_once('=').ok_or_else(invalid)?;
+        let (name, value) = (name.trim(), value.trim());
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) || value.is_empty() {
+            return Err(invalid());
+        }
+
+        let value = Tree::try_new(value)?.evaluate_with(&vars)?;
+        vars.insert(name.to_string(), value);
+    }
+
+    Ok((&s[..clause], vars))
+}
+
+/// Evaluate `input`, which may end in a `where` clause binding its variables
+///
+/// This never panics, whatever the input, every failure is reported through `EvalError`
+/// (which makes it the entry point for fuzzing)
+/// ```rust
+/// # use rational_calculator::{tree::evaluate_safe, EvalError, Value};
+/// assert_eq!(evaluate_safe("x^2 + x where x = 3"), Ok(Value::from(12)));
+/// assert_eq!(evaluate_safe("1/0"), Err(EvalError::DivideByZero));
+/// ```
+pub fn evaluate_safe(input: &str) -> Result<Value, EvalError> {
+    let (expression, vars) = parse_where(input)?;
+    Tree::try_new(expression)?.evaluate_with(&vars)
+}
+
+/// Whether `a` and `b` evaluate to the same value, so `1/2`, `2/4` and `0.5` are all equivalent
+pub fn expressions_equivalent(a: &str, b: &str) -> Result<bool, EvalError> {
+    let vars = HashMap::new();
+    Ok(Tree::try_new(a)?.evaluate_with(&vars)? == Tree::try_new(b)?.evaluate_with(&vars)?)
+}
+
+/// Evaluate `expr` and write the result in `style`
+/// ```rust
+/// # use rational_calculator::{config::DisplayStyle, tree::evaluate_formatted};
+/// assert_eq!(evaluate_formatted("7/2", DisplayStyle::Mixed), Ok("3 (1 / 2)".to_string()));
+/// ```
+pub fn evaluate_formatted(expr: &str, style: DisplayStyle) -> Result<String, EvalError> {
+    let value = Tree::try_new(expr)?.evaluate_with(&HashMap::new())?;
+    Ok(value.format(style))
+}
+
+/// Evaluate whitespace separated postfix input directly, `3 4 +` is `7`. Unary minus is written `u`
+/// (`5 u` is `-5`) and a word that isn't a token is reported as an invalid number
+/// ```rust
+/// # use rational_calculator::{tree::evaluate_rpn, Value};
+/// assert_eq!(evaluate_rpn("2 3 4 * +"), Ok(Value::from(14)));
+/// ```
+pub fn evaluate_rpn(input: &str) -> Result<Value, EvalError> {
+    let tokens = input
+        .split_whitespace()
+        .map(|word| {
+            word.parse::<Token>()
+                .map_err(|_| LexError::InvalidNumber(word.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Tree::try_from_tokens(tokens)?.evaluate_with(&HashMap::new())
+}
+
+/// Parse and evaluate `expr`, erroring with `EvalError::LimitExceeded` before anything is evaluated
+/// if the tree has more than `max_nodes` nodes or is more than `max_depth` deep
+/// ```rust
+/// # use rational_calculator::{tree::evaluate_with_limits, EvalError, Value};
+/// assert_eq!(evaluate_with_limits("1 + 2 * 3", 5, 3), Ok(Value::from(7)));
+/// assert_eq!(evaluate_with_limits("1 + 2 * 3", 4, 3), Err(EvalError::LimitExceeded));
+/// ```
+pub fn evaluate_with_limits(
+    expr: &str,
+    max_nodes: usize,
+    max_depth: usize,
+) -> Result<Value, EvalError> {
+    let tree = Tree::try_new(expr)?;
+    if tree.root.size() > max_nodes || tree.root.depth() > max_depth {
+        return Err(EvalError::LimitExceeded);
+    }
+    tree.evaluate_with(&HashMap::new())
+}
+
+/// Lazily evaluate each line of `reader` with `evaluate_safe`, skipping blank lines
+/// ```rust
+/// # use rational_calculator::{tree::evaluate_reader, Value};
+/// let results = evaluate_reader("1 + 1\n2 * 3\n".as_bytes()).collect::<Vec<_>>();
+/// assert_eq!(results, vec![Ok(Value::from(2)), Ok(Value::from(6))]);
+/// ```
+pub fn evaluate_reader<R: std::io::Read>(
+    reader: R,
+) -> impl Iterator<Item = Result<Value, EvalError>> {
+    BufReader::new(reader)
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| evaluate_safe(&line.map_err(|e| EvalError::Io(e.to_string()))?))
+}
+
+impl From<Vec<Token>> for Tree {
+    fn from(stream: Vec<Token>) -> Self {
+        Tree::try_from_tokens(stream).unwrap_or_else(|e| panic!("{}", e))
+    }
 }
 impl Debug for Tree {
     fn fmt(&self, f: &mut Formatter) -> fmt_Result {
@@ -144,6 +1441,16 @@ impl Display for Tree {
     }
 }
 
+#[cfg(test)]
+fn fraction(numerator: i64, denominator: i64) -> Value {
+    Value::new_rational(numerator, denominator).unwrap()
+}
+
+#[cfg(test)]
+fn evaluate(s: &str) -> Value {
+    Tree::new(s).evaluate()
+}
+
 #[test]
 fn test_tree_evaluate() {
     let mut problems: HashMap<&str, f64> = HashMap::new();
@@ -157,24 +1464,24 @@ fn test_tree_evaluate() {
     problems.insert("-12--10+-4+-6*-4", 18.0);
     problems.insert("5+-12-42/7*-10", 53.0);
 
-    // // medium
-    // problems.insert("(-2^3+-3)*-2--4+-3", 23.0);
-    // problems.insert("(54/9)^2-4*7+7", 15.0);
-    // problems.insert("(-3^2+-4)*-3--9+-4", -10.0);
-    // problems.insert("4-(8/4)^3*9+9", -59.0);
-    // problems.insert("6-(10/5)^2*-5+-5", 21.0);
+    // medium
+    problems.insert("(-2^3+-3)*-2--4+-3", 23.0);
+    problems.insert("(54/9)^2-4*7+7", 15.0);
+    problems.insert("(-3^2+-4)*-3--9+-4", -10.0);
+    problems.insert("4-(8/4)^3*9+9", -59.0);
+    problems.insert("6-(10/5)^2*-5+-5", 21.0);
 
-    // // hard
-    // problems.insert("(5-(9/3)^2)*6+6", -18.0);
-    // problems.insert("(10+(16/8))*3^3-8", 316.0);
-    // problems.insert("((4^2+-6)*4)-3+6", 43.0);
-    // problems.insert("(4-(-2^2-4))*(-2-8)", -40.0);
-    // problems.insert("((-78/-13)^3-8)*-4+4", -828.0);
+    // hard
+    problems.insert("(5-(9/3)^2)*6+6", -18.0);
+    problems.insert("(10+(16/8))*3^3-8", 316.0);
+    problems.insert("((4^2+-6)*4)-3+6", 43.0);
+    problems.insert("(4-(-2^2-4))*(-2-8)", -40.0);
+    problems.insert("((-78/-13)^3-8)*-4+4", -828.0);
 
     for (problem, answer) in problems.iter() {
         // eprintln!("Evaluating {}; expectms: u32ed: {}", problem, answer);
         let tree = Tree::new(problem);
-        // assert_eq!(tree.evaluate(), Value::from(*answer));
+        assert_eq!(tree.evaluate(), Value::from(*answer));
 
         // /* // DEBUG
         eprintln!(
@@ -190,3 +1497,1226 @@ fn test_tree_evaluate() {
         // */
     }
 }
+
+#[test]
+fn test_tree_fingerprint() {
+    assert_eq!(
+        Tree::new("(1 + 2) * 3").fingerprint(),
+        Tree::new("(1 + 2) * 3").fingerprint()
+    );
+    assert_eq!(
+        Tree::new("1+2").fingerprint(),
+        Tree::new("1 + 2").fingerprint()
+    );
+
+    assert_ne!(
+        Tree::new("1+2").fingerprint(),
+        Tree::new("2+1").fingerprint()
+    );
+    assert_ne!(
+        Tree::new("(1 + 2) * 3").fingerprint(),
+        Tree::new("1 + 2 * 3").fingerprint()
+    );
+    assert_eq!(
+        Tree::new("0.5").fingerprint(),
+        Tree::new("1/2").simplify().fingerprint()
+    );
+    // fixed, for fingerprints stored between runs
+    assert_eq!(Tree::new("1+2").fingerprint(), 1_252_831_104_462_634_926);
+}
+
+#[test]
+fn test_tree_operation_histogram() {
+    let histogram = Tree::new("1+2+3*4").operation_histogram();
+    assert_eq!(histogram.len(), 2);
+    assert_eq!(histogram[&Operator::Add], 2);
+    assert_eq!(histogram[&Operator::Mul], 1);
+
+    let histogram = Tree::new("-(2 - 1) / 4").operation_histogram();
+    assert_eq!(histogram[&Operator::USub], 1);
+    assert_eq!(histogram[&Operator::Sub], 1);
+    assert_eq!(histogram[&Operator::Div], 1);
+    assert_eq!(histogram.get(&Operator::Add), None);
+}
+
+#[test]
+fn test_tree_pow() {
+    assert_eq!(Tree::new("2^10").evaluate(), Value::from(1024));
+    assert_eq!(Tree::new("2^3^2").evaluate(), Value::from(512));
+
+    // negative exponents are exact reciprocals
+    assert_eq!(
+        Tree::new("2^-2").evaluate(),
+        Value::from(1) / Value::from(4)
+    );
+    assert_eq!(Tree::new("(1/2)^-3").evaluate(), Value::from(8));
+    assert_eq!(Tree::new("(2/3)^-1").evaluate(), Value::from(1.5));
+
+    // fractional bases stay exact
+    assert_eq!(
+        Tree::new("(2/3)^3").evaluate(),
+        Value::from(8) / Value::from(27)
+    );
+    assert_eq!(
+        Tree::new("(3/2)^2").evaluate(),
+        Value::from(9) / Value::from(4)
+    );
+    assert_eq!(
+        Tree::new("(-2/3)^3").evaluate(),
+        Value::from(-8) / Value::from(27)
+    );
+    assert_eq!(
+        Tree::new("(2/3)^-3").evaluate(),
+        Value::from(27) / Value::from(8)
+    );
+    assert_eq!(
+        Tree::new("0.1^18").evaluate(),
+        Value::from(1) / Value::from(10i64.pow(18))
+    );
+}
+
+#[test]
+#[should_panic(expected = "Divide by zero")]
+fn test_tree_pow_zero_base() {
+    Tree::new("0^-2").evaluate();
+}
+
+#[test]
+fn test_tree_modulo() {
+    assert_eq!(Tree::new("7 % 3").evaluate(), Value::from(1));
+    assert_eq!(Tree::new("1 + 10 % 4 * 2").evaluate(), Value::from(5));
+
+    // floored by default
+    assert_eq!(Tree::new("-7 % 3").evaluate(), Value::from(2));
+
+    let floored = EvalMode {
+        modulo: Modulo::Floored,
+        ..EvalMode::default()
+    };
+    let truncated = EvalMode {
+        modulo: Modulo::Truncated,
+        ..EvalMode::default()
+    };
+    assert_eq!(
+        Tree::new("-7 % 3").evaluate_with_mode(floored),
+        Value::from(2)
+    );
+    assert_eq!(
+        Tree::new("-7 % 3").evaluate_with_mode(truncated),
+        Value::from(-1)
+    );
+    assert_eq!(
+        Tree::new("7 % -3").evaluate_with_mode(floored),
+        Value::from(-2)
+    );
+    assert_eq!(
+        Tree::new("7 % -3").evaluate_with_mode(truncated),
+        Value::from(1)
+    );
+}
+
+#[test]
+fn test_tree_max_denominator() {
+    let capped = EvalMode {
+        max_denominator: Some(1000),
+        ..EvalMode::default()
+    };
+    let tree = Tree::new("1/2 + 1/3 + 1/5 + 1/7 + 1/11");
+    // 247/210 still fits, the exact 2927/2310 doesn't
+    assert_eq!(
+        Tree::new("1/2 + 1/3 + 1/5 + 1/7").evaluate_with_mode(capped),
+        fraction(247, 210)
+    );
+    assert_eq!(tree.evaluate_with_mode(capped), fraction(389, 307));
+    assert_eq!(
+        tree.evaluate_with_mode(EvalMode::default()),
+        fraction(2927, 2310)
+    );
+}
+
+#[test]
+fn test_tree_non_finite() {
+    let mode = EvalMode {
+        non_finite: true,
+        ..EvalMode::default()
+    };
+    let evaluate = |s: &str| Tree::new(s).evaluate_with_mode(mode);
+
+    assert_eq!(evaluate("1/0"), Value::Infinity);
+    assert_eq!(evaluate("-1/0"), Value::NegInfinity);
+    assert_eq!(evaluate("0/0"), Value::Undefined);
+    assert_eq!(evaluate("1/0 + 1"), Value::Infinity);
+    assert_eq!(evaluate("1/0 - 1/0"), Value::Undefined);
+    assert_eq!(evaluate("2 / (1/0)"), Value::from(0));
+    assert_eq!(evaluate("-(1/0) * 3"), Value::NegInfinity);
+    assert_eq!(evaluate("5 % 0"), Value::Undefined);
+    assert_eq!(evaluate("0^-1"), Value::Infinity);
+
+    // off by default
+    assert_eq!(
+        Tree::new("1/0").evaluate_with(&HashMap::new()),
+        Err(EvalError::DivideByZero)
+    );
+}
+
+#[test]
+fn test_tree_evaluate_with() {
+    let mut vars = HashMap::new();
+    vars.insert("x".to_string(), Value::from(3));
+    vars.insert("half".to_string(), Value::from(0.5));
+
+    assert_eq!(
+        Tree::new("x^2 + x").evaluate_with(&vars),
+        Ok(Value::from(12))
+    );
+    assert_eq!(
+        Tree::new("x * half").evaluate_with(&vars),
+        Ok(Value::from(1.5))
+    );
+    assert_eq!(
+        Tree::new("x + y").evaluate_with(&vars),
+        Err(EvalError::UnknownVariable("y".to_string()))
+    );
+}
+
+#[test]
+fn test_evaluate_where() {
+    assert_eq!(evaluate_safe("x^2 + x where x = 3"), Ok(Value::from(12)));
+    assert_eq!(
+        evaluate_safe("x*y - z where x = 2, y = 3, z = 1"),
+        Ok(Value::from(5))
+    );
+    assert_eq!(
+        evaluate_safe("a + b where a = 1/2, b = a * 2"),
+        Ok(Value::from(1.5))
+    );
+    assert_eq!(evaluate_safe("1 + 2"), Ok(Value::from(3)));
+
+    assert_eq!(
+        evaluate_safe("x + 1 where y = 2"),
+        Err(EvalError::UnknownVariable("x".to_string()))
+    );
+    assert_eq!(
+        evaluate_safe("x + 1 where x"),
+        Err(EvalError::InvalidBinding("x".to_string()))
+    );
+    assert_eq!(
+        evaluate_safe("x + 1 where 2 = x"),
+        Err(EvalError::InvalidBinding("2 = x".to_string()))
+    );
+}
+
+#[test]
+fn test_tree_floor_div() {
+    assert_eq!(Tree::new("7 // 2").evaluate(), Value::from(3));
+    assert_eq!(Tree::new("-7 // 2").evaluate(), Value::from(-4));
+    assert_eq!(Tree::new("7 // -2").evaluate(), Value::from(-4));
+    assert_eq!(Tree::new("6 // 3").evaluate(), Value::from(2));
+    assert_eq!(Tree::new("(7/2) // (1/3)").evaluate(), Value::from(10));
+    assert_eq!(Tree::new("1 + 9 // 2 * 2").evaluate(), Value::from(9));
+}
+
+#[test]
+fn test_tree_double_star_pow() {
+    assert_eq!(Tree::new("2**3").evaluate(), Value::from(8));
+    assert_eq!(Tree::new("2*3").evaluate(), Value::from(6));
+    assert_eq!(
+        Tree::new("2**3**2").evaluate(),
+        Tree::new("2^3^2").evaluate()
+    );
+    assert_eq!(Tree::new("3*2**2").evaluate(), Value::from(12));
+    assert_eq!(
+        Tree::try_new("2***3").err(),
+        Some(EvalError::MissingOperand)
+    );
+}
+
+#[test]
+fn test_tree_simplify() {
+    let simplified = Tree::new("2+3*4").simplify();
+    assert_eq!(simplified.root.token, Token::Value(14.into()));
+    assert!(simplified.root.left.is_none() && simplified.root.right.is_none());
+
+    let simplified = |s: &str| format!("{:?}", Tree::new(s).simplify());
+    assert_eq!(simplified("x*1"), "x");
+    assert_eq!(simplified("1*x"), "x");
+    assert_eq!(simplified("x+0"), "x");
+    assert_eq!(simplified("0+x"), "x");
+    assert_eq!(simplified("x-(2-2)"), "x");
+    assert_eq!(simplified("x/1"), "x");
+    assert_eq!(simplified("x^(3-2)"), "x");
+    assert_eq!(simplified("x*0"), "0");
+    assert_eq!(simplified("(2-2)*(x+y)"), "0");
+    assert_eq!(simplified("x*(1+1)+-3"), "((x * 2) + -3)");
+    assert_eq!(simplified("0-x"), "(0 - x)");
+}
+
+#[test]
+fn test_tree_to_latex() {
+    let latex = |s: &str| Tree::new(s).to_latex();
+
+    assert_eq!(latex("1/2"), "\\frac{1}{2}");
+    assert_eq!(latex("(1+2)*3"), "\\left(1 + 2\\right) \\cdot 3");
+    assert_eq!(latex("1+2*3"), "1 + 2 \\cdot 3");
+    assert_eq!(latex("(1+2)/(3-x)"), "\\frac{1 + 2}{3 - x}");
+    assert_eq!(latex("1-(2-3)"), "1 - \\left(2 - 3\\right)");
+    assert_eq!(latex("(1-2)-3"), "1 - 2 - 3");
+    assert_eq!(latex("2*-3"), "2 \\cdot \\left(-3\\right)");
+    assert_eq!(latex("-(1+2)"), "-\\left(1 + 2\\right)");
+    assert_eq!(latex("(x+1)^2"), "\\left(x + 1\\right)^{2}");
+    assert_eq!(latex("-2^2"), "\\left(-2\\right)^{2}");
+    assert_eq!(latex("2^(1+1)"), "2^{1 + 1}");
+    assert_eq!(latex("7//2"), "\\left\\lfloor \\frac{7}{2} \\right\\rfloor");
+    assert_eq!(latex("7%(1+2)"), "7 \\bmod \\left(1 + 2\\right)");
+}
+
+#[test]
+fn test_evaluate_safe() {
+    let deep_nesting = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+    let long_chain = "1+".repeat(100_000) + "1";
+    let negations = "-".repeat(100_000) + "1";
+    let adversarial = vec![
+        ")",
+        "(",
+        "1/0",
+        "1//0",
+        "1%0",
+        "0^-1",
+        "*",
+        "",
+        "   ",
+        "-",
+        "1+",
+        "+1",
+        "()",
+        "(1+2",
+        "1+2)",
+        "2(3)",
+        ".",
+        "1.2.3",
+        "99999999999999999999999999",
+        "9223372036854775807+1",
+        "-9223372036854775807-2",
+        "9223372036854775807*9223372036854775807",
+        "(1/9223372036854775807)*(1/3)",
+        "2^64",
+        "2^99999999999",
+        "2^(1/2)",
+        "0.000000000000000000001",
+        "x",
+        "where",
+        "x where",
+        "x where x",
+        "x where x = ",
+        "x where = 1",
+        "x where x = (",
+        "$1",
+        "ü+∞",
+        "|-9223372036854775807-1|",
+        "abs(-9223372036854775807-1)",
+        &deep_nesting,
+        &long_chain,
+        &negations,
+    ];
+
+    for input in adversarial {
+        let _ = evaluate_safe(input);
+    }
+
+    assert_eq!(evaluate_safe("1/0"), Err(EvalError::DivideByZero));
+    assert_eq!(evaluate_safe(""), Err(EvalError::EmptyExpression));
+    assert_eq!(evaluate_safe("*"), Err(EvalError::MissingOperand));
+    assert_eq!(evaluate_safe("(1+2"), Err(EvalError::UnbalancedParens));
+    assert_eq!(evaluate_safe("2(3)"), Err(EvalError::MissingOperator));
+    assert_eq!(evaluate_safe("2^64"), Err(EvalError::Overflow));
+    assert_eq!(evaluate_safe("2^(1/2)"), Err(EvalError::NotAnInteger));
+    assert_eq!(
+        evaluate_safe("|-9223372036854775807-1|"),
+        Err(EvalError::Overflow)
+    );
+    assert_eq!(
+        evaluate_safe("abs(-9223372036854775807-1)"),
+        Err(EvalError::Overflow)
+    );
+    assert_eq!(evaluate_safe(&long_chain), Ok(Value::from(100_001)));
+    assert_eq!(evaluate_safe(&negations), Ok(Value::from(1)));
+    assert_eq!(evaluate_safe(&deep_nesting), Ok(Value::from(1)));
+    assert_eq!(
+        evaluate_safe("1.2.3"),
+        Err(EvalError::Lex(crate::LexError::InvalidNumber(
+            "1.2.3".to_string()
+        )))
+    );
+    assert_eq!(evaluate_safe("(1+2)*3"), Ok(Value::from(9)));
+}
+
+#[test]
+fn test_tree_interval() {
+    let mut ranges = HashMap::new();
+    ranges.insert("x".to_string(), (Value::from(0), Value::from(10)));
+    ranges.insert("y".to_string(), (Value::from(-2), Value::from(3)));
+    ranges.insert("z".to_string(), (Value::from(1), Value::from(4)));
+
+    let interval = |s: &str| Tree::new(s).interval_with(&ranges);
+    assert_eq!(interval("x + 1"), Ok((Value::from(1), Value::from(11))));
+    assert_eq!(interval("1 - x"), Ok((Value::from(-9), Value::from(1))));
+    assert_eq!(interval("x * y"), Ok((Value::from(-20), Value::from(30))));
+    assert_eq!(interval("-y"), Ok((Value::from(-3), Value::from(2))));
+    assert_eq!(interval("x / z"), Ok((Value::from(0), Value::from(10))));
+    assert_eq!(interval("1 / z"), Ok((Value::from(0.25), Value::from(1))));
+    assert_eq!(interval("x / y"), Err(EvalError::DivideByZero));
+    assert_eq!(interval("x % 3"), Err(EvalError::OutOfDomain));
+    assert_eq!(interval("|y|"), Ok((Value::from(0), Value::from(3))));
+    assert_eq!(interval("|y - 5|"), Ok((Value::from(2), Value::from(7))));
+    assert_eq!(
+        interval("x + w"),
+        Err(EvalError::UnknownVariable("w".to_string()))
+    );
+
+    // without variables the interval is the exact value
+    let exact = Tree::new("(1 + 2) * 3 % 4 / 2").interval();
+    assert_eq!(exact, Ok((Value::from(0.5), Value::from(0.5))));
+}
+
+#[test]
+fn test_tree_implicit_mul() {
+    let implicit = ParseOptions {
+        implicit_mul: true,
+        ..ParseOptions::default()
+    };
+
+    assert_eq!(
+        Tree::try_new_with_options("2(3)", ParseOptions::default()).err(),
+        Some(EvalError::MissingOperator)
+    );
+    assert_eq!(
+        Tree::try_new("2(3)").err(),
+        Some(EvalError::MissingOperator)
+    );
+    assert_eq!(Tree::new_with_options("2(3)", implicit).evaluate(), 6);
+    assert_eq!(
+        Tree::new_with_options("(1+2)(3+4) - 2(5)", implicit).evaluate(),
+        11
+    );
+    // rather than repeating decimals
+    assert_eq!(
+        Tree::new_with_options("1.5(3)", implicit).evaluate(),
+        fraction(9, 2)
+    );
+    assert_eq!(Tree::new_with_options("0.5(2)", implicit).evaluate(), 1);
+    assert_eq!(Tree::new("0.5(2)").evaluate(), fraction(47, 90));
+}
+
+#[test]
+fn test_tree_evaluate_with_error() {
+    let (exact, error) = Tree::new("0.1+0.2").evaluate_with_error();
+    assert_eq!(exact, Value::from(3) / Value::from(10));
+    assert!(error > 0.0);
+    assert!(error < 1e-15);
+
+    assert_eq!(
+        Tree::new("(1 + 2) * 3 // 2").evaluate_with_error(),
+        (Value::from(4), 0.0)
+    );
+}
+
+#[test]
+fn test_tree_units() {
+    let units = ParseOptions {
+        units: true,
+        ..ParseOptions::default()
+    };
+    let evaluate = |s: &str| Tree::new_with_options(s, units).evaluate();
+
+    assert_eq!(evaluate("3k"), 3000);
+    assert_eq!(evaluate("1.5M"), 1500000);
+    assert_eq!(evaluate("-2k + 0.25k"), -1750);
+    assert_eq!(evaluate("2G / 4M"), 500);
+    assert_eq!(Tree::try_new("3k").err(), Some(EvalError::MissingOperator));
+
+    // a unit on its own is still a variable
+    let vars = vec![("k".to_string(), Value::from(2))]
+        .into_iter()
+        .collect();
+    assert_eq!(
+        Tree::new_with_options("k * 3k", units).evaluate_with(&vars),
+        Ok(Value::from(6000))
+    );
+}
+
+#[test]
+fn test_tree_evaluate_debug() {
+    assert_eq!(
+        Tree::new("3 + 4").evaluate_debug(),
+        vec!["[3]", "[3, 4]", "[7]"]
+    );
+    assert_eq!(
+        Tree::new("2 * -(1 + 0.5)").evaluate_debug(),
+        vec![
+            "[2]",
+            "[2, 1]",
+            "[2, 1, 0 (1 / 2)]",
+            "[2, 1 (1 / 2)]",
+            "[2, -1 (-1 / 2)]",
+            "[-3]"
+        ]
+    );
+    assert_eq!(
+        Tree::new("1 + 1 / 0").evaluate_debug(),
+        vec!["[1]", "[1, 1]", "[1, 1, 0]", "Error: Divide by zero"]
+    );
+}
+
+#[test]
+fn test_tree_evaluate_with_functions() {
+    let mut functions = FunctionRegistry::new();
+    functions.insert("double".to_string(), |v| v.try_mul(Value::from(2)));
+    functions.insert("recip".to_string(), |v| Value::from(1).try_div(v));
+
+    let evaluate = |s: &str| Tree::new(s).evaluate_with_functions(&functions);
+    assert_eq!(evaluate("double(21)"), Ok(Value::from(42)));
+    assert_eq!(evaluate("double(double(1) + 1) ^ 2"), Ok(Value::from(36)));
+    assert_eq!(evaluate("-recip(4) * 2"), Ok(Value::from(-0.5)));
+    assert_eq!(evaluate("recip(0)"), Err(EvalError::DivideByZero));
+    assert_eq!(
+        evaluate("triple(1)"),
+        Err(EvalError::UnknownFunction("triple".to_string()))
+    );
+    assert_eq!(
+        Tree::new("double(21)").evaluate_with(&HashMap::new()),
+        Err(EvalError::UnknownFunction("double".to_string()))
+    );
+    assert_eq!(
+        Tree::try_new("double()").err(),
+        Some(EvalError::MissingOperand)
+    );
+    assert_eq!(
+        Tree::try_new("double(1").err(),
+        Some(EvalError::UnbalancedParens)
+    );
+}
+
+#[test]
+fn test_tree_require_operators() {
+    let require = ParseOptions {
+        require_operators: true,
+        implicit_mul: true,
+        ..ParseOptions::default()
+    };
+
+    assert_eq!(Tree::new("2 3").evaluate(), 23);
+    assert_eq!(
+        Tree::try_new_with_options("2 3", require).err(),
+        Some(EvalError::Lex(crate::LexError::MissingOperator(2)))
+    );
+    assert_eq!(
+        Tree::try_new_with_options("(1)(2)", require).err(),
+        Some(EvalError::Lex(crate::LexError::MissingOperator(3)))
+    );
+    assert_eq!(Tree::new_with_options("2 * 3", require).evaluate(), 6);
+}
+
+#[test]
+fn test_tree_conditional() {
+    assert_eq!(evaluate("1 ? 10 : 20"), 10);
+    assert_eq!(evaluate("0 ? 10 : 20"), 20);
+    assert_eq!(evaluate("0.5 ? 10 : 20"), 10);
+    assert_eq!(evaluate("2 - 2 ? 10 : 20 + 1"), 21);
+    assert_eq!(evaluate("0 ? 1 : 0 ? 2 : 3"), 3);
+    assert_eq!(evaluate("1 ? 0 ? 1 : 2 : 3"), 2);
+    assert_eq!(evaluate("(1 ? 0 : 1) ? 2 : 3"), 3);
+    assert_eq!(evaluate("-(1 ? 2 : 3) * 2"), -4);
+
+    // the branch that isn't taken isn't evaluated
+    assert_eq!(evaluate("1 ? 10 : 1 / 0"), 10);
+    assert_eq!(
+        Tree::new("0 ? x : 1").evaluate_with(&HashMap::new()),
+        Ok(Value::from(1))
+    );
+
+    for s in &["1 ? 2", "1 : 2", "1 + (2 : 3)", "(1 ? 2) : 3"] {
+        assert_eq!(
+            Tree::try_new(s).err(),
+            Some(EvalError::InvalidConditional),
+            "{}",
+            s
+        );
+    }
+
+    assert_eq!(
+        Tree::new("0 ? x : 1 + 1").simplify().evaluate(),
+        Value::from(2)
+    );
+    assert_eq!(
+        Tree::new("x ? 1 + 1 : y").simplify().to_latex(),
+        "x \\mathbin{?} 2 : y"
+    );
+
+    let mut ranges = HashMap::new();
+    ranges.insert("x".to_string(), (Value::from(-1), Value::from(1)));
+    assert_eq!(
+        Tree::new("x ? x + 5 : 2").interval_with(&ranges),
+        Ok((Value::from(2), Value::from(6)))
+    );
+    assert_eq!(
+        Tree::new("x + 2 ? 1 : 1 / 0").interval_with(&ranges),
+        Ok((Value::from(1), Value::from(1)))
+    );
+
+    assert_eq!(
+        Tree::new("1 ? 2 : 3").evaluate_debug(),
+        vec!["[1]", "[1, 2]", "[1, 2, 3]", "[1, 2, 3]", "[2]"]
+    );
+}
+
+#[test]
+fn test_tree_surrounding_whitespace() {
+    assert_eq!(Tree::new("2+2\n").evaluate(), 4);
+    assert_eq!(Tree::new("  2+2  ").evaluate(), 4);
+    assert_eq!(Tree::new("\t2+2\r\n").evaluate(), 4);
+    assert_eq!(
+        Tree::new("2+2\n").fingerprint(),
+        Tree::new("  2+2  ").fingerprint()
+    );
+    assert_eq!(Tree::try_new(" \n").err(), Some(EvalError::EmptyExpression));
+}
+
+#[test]
+fn test_tree_has_nonexact_division() {
+    assert!(!Tree::new("8/4").has_nonexact_division());
+    assert!(Tree::new("7/4").has_nonexact_division());
+    assert!(!Tree::new("1 + 2 * 3").has_nonexact_division());
+    // the fraction in the middle is flagged even though the result is whole
+    assert!(Tree::new("(7/4) * 4").has_nonexact_division());
+    assert!(!Tree::new("(6/2 + 3) / 2").has_nonexact_division());
+    assert!(!Tree::new("7 // 4").has_nonexact_division());
+    assert!(!Tree::new("7 / x").has_nonexact_division());
+}
+
+#[test]
+fn test_tree_superscript_pow() {
+    assert_eq!(Tree::new("5²").evaluate(), Value::from(25));
+    assert_eq!(Tree::new("2³").evaluate(), Value::from(8));
+    assert_eq!(Tree::new("2¹⁰ - 1").evaluate(), Value::from(1023));
+    assert_eq!(Tree::new("(1/2)² * 3").evaluate(), Value::from(0.75));
+    assert_eq!(Tree::new("2 * 3²").evaluate(), Value::from(18));
+    // exponents too large for anything but 0 and ±1
+    assert_eq!(Tree::new("1²²²²²²²²²²²").evaluate(), Value::from(1));
+    assert_eq!(Tree::new("0²²²²²²²²²²²").evaluate(), Value::from(0));
+    assert_eq!(
+        Tree::new("(-1)²²²²²²²²²²²²²²²²²²²²").evaluate(),
+        Value::from(1)
+    );
+    assert_eq!(
+        Tree::new("(-1)³³³³³³³³³³³³³³³³³³³³").evaluate(),
+        Value::from(-1)
+    );
+    assert_eq!(
+        Tree::new("2²²²²²²²²²²²").evaluate_lossy(),
+        Err(EvalError::Overflow)
+    );
+}
+
+#[test]
+fn test_tree_associativity() {
+    let cases = [
+        // left-associative chains group from the left
+        ("10-3-2", Value::from(5)),
+        ("10-3+2", Value::from(9)),
+        ("10+3-2", Value::from(11)),
+        ("100/10/2", Value::from(5)),
+        ("100/10*2", Value::from(20)),
+        ("2*3/4*2", Value::from(3)),
+        ("7//2//2", Value::from(1)),
+        ("100%30%7", Value::from(3)),
+        ("1-2-3-4", Value::from(-8)),
+        ("64/4/4/2", Value::from(2)),
+        // right-associative chains group from the right
+        ("2^3^2", Value::from(512)),
+        ("2^1^3", Value::from(2)),
+        ("2^-1", Value::from(0.5)),
+        ("--3", Value::from(3)),
+        // mixed precedence
+        ("2^3*2", Value::from(16)),
+        ("2*2^3", Value::from(16)),
+        ("12/2^2", Value::from(3)),
+        ("10-2^2-1", Value::from(5)),
+        ("1 ? 2 : 0 ? 3 : 4", Value::from(2)),
+        ("0 ? 2 : 0 ? 3 : 4", Value::from(4)),
+    ];
+
+    for (expression, expected) in cases.iter() {
+        assert_eq!(
+            Tree::new(expression).evaluate(),
+            *expected,
+            "{}",
+            expression
+        );
+    }
+}
+
+#[test]
+fn test_evaluate_with_limits() {
+    // 1 + (2 * 3) is 5 nodes, 3 deep
+    assert_eq!(evaluate_with_limits("1 + 2 * 3", 5, 3), Ok(Value::from(7)));
+    assert_eq!(
+        evaluate_with_limits("1 + 2 * 3", 100, 100),
+        Ok(Value::from(7))
+    );
+    assert_eq!(
+        evaluate_with_limits("1 + 2 * 3", 4, 3),
+        Err(EvalError::LimitExceeded)
+    );
+    assert_eq!(
+        evaluate_with_limits("1 + 2 * 3", 5, 2),
+        Err(EvalError::LimitExceeded)
+    );
+
+    // nothing is evaluated once a limit is hit
+    assert_eq!(
+        evaluate_with_limits("1/0 + 1/0", 3, 10),
+        Err(EvalError::LimitExceeded)
+    );
+    assert_eq!(
+        evaluate_with_limits("1/0", 3, 10),
+        Err(EvalError::DivideByZero)
+    );
+
+    let long_chain = vec!["1"; 50].join("+");
+    assert_eq!(
+        evaluate_with_limits(&long_chain, 1000, 10),
+        Err(EvalError::LimitExceeded)
+    );
+    assert_eq!(
+        evaluate_with_limits(&long_chain, 99, 1000),
+        Ok(Value::from(50))
+    );
+}
+
+#[test]
+fn test_tree_evaluate_lossy() {
+    assert_eq!(
+        Tree::new("1/3 + 1").evaluate_lossy(),
+        Ok((Value::from(4) / Value::from(3), false))
+    );
+
+    // 2^64 doesn't fit, but the float result of the whole expression does
+    assert_eq!(
+        Tree::new("2^62 * 4 / 2^61").evaluate_lossy(),
+        Ok((Value::from(8), true))
+    );
+    assert_eq!(
+        Tree::new("(2^62 * 4) / 2^63 + 1/2").evaluate_lossy(),
+        Ok((Value::from(2.5), true))
+    );
+
+    // errors the float fallback can't help with are passed on
+    assert_eq!(
+        Tree::new("1/0").evaluate_lossy(),
+        Err(EvalError::DivideByZero)
+    );
+    assert_eq!(
+        Tree::new("x + 1").evaluate_lossy(),
+        Err(EvalError::UnknownVariable("x".to_string()))
+    );
+    assert_eq!(
+        Tree::new("2^64 * 2^64 * 2^64 * 2^64 * 2^64").evaluate_lossy(),
+        Err(EvalError::Overflow)
+    );
+    assert_eq!(
+        Tree::new("|2^62 * -4| / 2^61").evaluate_lossy(),
+        Ok((Value::from(8), true))
+    );
+}
+
+#[test]
+fn test_tree_fractions_only() {
+    let options = ParseOptions {
+        fractions_only: true,
+        ..ParseOptions::default()
+    };
+
+    assert_eq!(
+        Tree::try_new_with_options("0.5", options).map(|tree| tree.evaluate()),
+        Err(EvalError::Lex(LexError::DecimalNotAllowed(1)))
+    );
+    assert_eq!(
+        Tree::try_new_with_options("1 + 2.", options).map(|tree| tree.evaluate()),
+        Err(EvalError::Lex(LexError::DecimalNotAllowed(5)))
+    );
+    assert_eq!(
+        Tree::new_with_options("1/2", options).evaluate(),
+        Value::from(0.5)
+    );
+    assert_eq!(
+        Tree::new_with_options("3_1/2 * 2", options).evaluate(),
+        Value::from(7)
+    );
+    assert_eq!(Tree::new("0.5").evaluate(), Value::from(1) / Value::from(2));
+}
+
+#[test]
+fn test_evaluate_rpn() {
+    assert_eq!(evaluate_rpn("3 4 +"), Ok(Value::from(7)));
+    assert_eq!(evaluate_rpn("10 3 - 2 -"), Ok(Value::from(5)));
+    assert_eq!(evaluate_rpn("1 2 / 5 u *"), Ok(Value::from(-2.5)));
+    assert_eq!(evaluate_rpn("  7 2 //  "), Ok(Value::from(3)));
+
+    assert_eq!(evaluate_rpn("3 +"), Err(EvalError::MissingOperand));
+    assert_eq!(evaluate_rpn("3 4"), Err(EvalError::MissingOperator));
+    assert_eq!(evaluate_rpn(""), Err(EvalError::EmptyExpression));
+    assert_eq!(
+        evaluate_rpn("3 4 $"),
+        Err(EvalError::Lex(LexError::InvalidNumber("$".to_string())))
+    );
+}
+
+#[test]
+fn test_tree_unary_plus() {
+    assert_eq!(Tree::new("+5").evaluate(), Value::from(5));
+    assert_eq!(Tree::new("3 * +2").evaluate(), Value::from(6));
+    assert_eq!(Tree::new("2 - +3").evaluate(), Value::from(-1));
+    assert_eq!(Tree::new("+(1/2)").evaluate(), Value::from(0.5));
+}
+
+#[test]
+fn test_evaluate_formatted() {
+    assert_eq!(
+        evaluate_formatted("1/2+1/2", DisplayStyle::Fraction),
+        Ok("1".to_string())
+    );
+    assert_eq!(
+        evaluate_formatted("-7/2", DisplayStyle::Fraction),
+        Ok("-7/2".to_string())
+    );
+    assert_eq!(
+        evaluate_formatted("1/4 + 3", DisplayStyle::Mixed),
+        Ok("3 (1 / 4)".to_string())
+    );
+    assert_eq!(
+        evaluate_formatted("1/8", DisplayStyle::Decimal),
+        Ok("0.1250000000".to_string())
+    );
+    assert_eq!(
+        evaluate_formatted("1/0", DisplayStyle::Fraction),
+        Err(EvalError::DivideByZero)
+    );
+}
+
+#[test]
+fn test_tree_nested_functions() {
+    let mut functions = FunctionRegistry::new();
+    functions.insert("sqrt".to_string(), |v| {
+        if v < Value::from(0) {
+            return Err(EvalError::OutOfDomain);
+        }
+        Value::checked_from_f64(v.to_f64().sqrt()).ok_or(EvalError::Overflow)
+    });
+    functions.insert("abs".to_string(), |v| Ok(v.abs()));
+    functions.insert("floor".to_string(), |v| Ok(v.floor()));
+
+    let evaluate = |s: &str| Tree::new(s).evaluate_with_functions(&functions);
+    assert_eq!(evaluate("sqrt(abs(-16))"), Ok(Value::from(4)));
+    assert_eq!(evaluate("floor(sqrt(10))"), Ok(Value::from(3)));
+    assert_eq!(evaluate("sqrt((3+1)*4)"), Ok(Value::from(4)));
+    assert_eq!(evaluate("abs(floor(-sqrt(2))) + 1"), Ok(Value::from(3)));
+    assert_eq!(evaluate("floor(abs(sqrt(81)) / 2) * 2"), Ok(Value::from(8)));
+    assert_eq!(evaluate("sqrt(-abs(4))"), Err(EvalError::OutOfDomain));
+}
+
+#[test]
+fn test_expressions_equivalent() {
+    assert_eq!(expressions_equivalent("1/2", "2/4"), Ok(true));
+    assert_eq!(expressions_equivalent("2/4", "0.5"), Ok(true));
+    assert_eq!(expressions_equivalent("(1+2)*3", "9"), Ok(true));
+    assert_eq!(expressions_equivalent("3_1/2", "7/2"), Ok(true));
+    assert_eq!(expressions_equivalent("6/3", "2"), Ok(true));
+
+    assert_eq!(expressions_equivalent("1/3", "0.333"), Ok(false));
+    assert_eq!(expressions_equivalent("1-2-3", "1-(2-3)"), Ok(false));
+    assert_eq!(expressions_equivalent("-1/2", "1/2"), Ok(false));
+
+    assert_eq!(
+        expressions_equivalent("1/0", "1"),
+        Err(EvalError::DivideByZero)
+    );
+    assert_eq!(
+        expressions_equivalent("1", "x"),
+        Err(EvalError::UnknownVariable("x".to_string()))
+    );
+    assert_eq!(
+        expressions_equivalent("1", "(2"),
+        Err(EvalError::UnbalancedParens)
+    );
+}
+
+#[test]
+fn test_tree_evaluate_timeout() {
+    let tree = Tree::new(&vec!["(1/2 + 1/3)"; 100].join(" * 6/5 * "));
+
+    assert_eq!(
+        tree.evaluate_timeout(Duration::ZERO),
+        Err(EvalError::Timeout)
+    );
+    assert_eq!(
+        Tree::new("1 + 2").evaluate_timeout(Duration::from_secs(60)),
+        Ok(Value::from(3))
+    );
+}
+
+#[test]
+fn test_evaluate_reader() {
+    let input = std::io::Cursor::new("1/2 + 1/4\n\n  (2 + 3) * 4\nx^2 where x = 3\n1/0\n7 // 2");
+    assert_eq!(
+        evaluate_reader(input).collect::<Vec<_>>(),
+        vec![
+            Ok(Value::from(0.75)),
+            Ok(Value::from(20)),
+            Ok(Value::from(9)),
+            Err(EvalError::DivideByZero),
+            Ok(Value::from(3)),
+        ]
+    );
+
+    // invalid UTF-8 is reported for its line and the rest are still read
+    let input = std::io::Cursor::new(b"1\n\xff\n2\n".to_vec());
+    let results = evaluate_reader(input).collect::<Vec<_>>();
+    assert_eq!(results.len(), 3);
+    assert!(matches!(results[1], Err(EvalError::Io(_))));
+    assert_eq!(results[2], Ok(Value::from(2)));
+}
+
+#[test]
+fn test_tree_simplify_keeps_errors() {
+    // folding the constants would divide by zero, so they're left for evaluation to report
+    let simplified = Tree::new("1/0 + 2*3").simplify();
+    assert_eq!(
+        simplified.evaluate_with(&HashMap::new()),
+        Err(EvalError::DivideByZero)
+    );
+    assert_eq!(
+        Tree::new("2^64 - 1")
+            .simplify()
+            .evaluate_with(&HashMap::new()),
+        Err(EvalError::Overflow)
+    );
+    for zeroed in &["(1/0)*0", "0*(1/0)", "(2^64)*0", "(x + 1/0)*0"] {
+        let simplified = Tree::new(zeroed).simplify();
+        assert_eq!(
+            simplified.evaluate_with(&HashMap::new()),
+            Tree::new(zeroed).evaluate_with(&HashMap::new())
+        );
+        assert!(simplified.evaluate_with(&HashMap::new()).is_err());
+    }
+    assert_eq!(
+        Tree::try_new("-(-9223372036854775807-1)")
+            .unwrap()
+            .simplify()
+            .evaluate_with(&HashMap::new()),
+        Err(EvalError::Overflow)
+    );
+}
+
+#[test]
+fn test_tree_abs_bars() {
+    assert_eq!(Tree::new("|−5|").evaluate(), Value::from(5));
+    assert_eq!(Tree::new("|3−7|").evaluate(), Value::from(4));
+    assert_eq!(Tree::new("|-2| * |1/2 - 2|").evaluate(), Value::from(3));
+    assert_eq!(Tree::new("||-5| - 8|").evaluate(), Value::from(3));
+    assert_eq!(
+        Tree::new("abs(-1/3)").evaluate(),
+        Value::from(1) / Value::from(3)
+    );
+    assert_eq!(
+        Tree::new("|-3| + 1")
+            .evaluate_debug()
+            .last()
+            .map(String::as_str),
+        Some("[4]")
+    );
+    assert_eq!(Tree::try_new("|3").err(), Some(EvalError::UnbalancedParens));
+}
+
+#[test]
+fn test_tree_nested_fractions() {
+    assert_eq!(Tree::new("(1/2)/(3/4)").evaluate(), fraction(2, 3));
+    assert_eq!(Tree::new("(3/2)/(1/2)").evaluate(), Value::from(3));
+    // both operands with nonzero quotients
+    assert_eq!(Tree::new("(7/3)/(5/2)").evaluate(), fraction(14, 15));
+    assert_eq!(Tree::new("(-9/4)/(3/2)").evaluate(), fraction(-3, 2));
+    assert_eq!(Tree::new("((1/2)/(1/3))/(3/4)").evaluate(), Value::from(2));
+    assert_eq!(fraction(3, 2) / fraction(1, 2), Value::from(3));
+}
+
+#[test]
+fn test_tree_repeated_unary_minus() {
+    assert_eq!(Tree::new("3 - - 2").evaluate(), Value::from(5));
+    assert_eq!(Tree::new("3 - - - 2").evaluate(), Value::from(1));
+    assert_eq!(Tree::new("- - - 2").evaluate(), Value::from(-2));
+    assert_eq!(Tree::new("- - - - 2").evaluate(), Value::from(2));
+    assert_eq!(Tree::new("2 * - - (1 - 4)").evaluate(), Value::from(-6));
+    assert_eq!(Tree::new("- - 2 ^ 2").evaluate(), Value::from(4));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_tree_json() {
+    let tree = Tree::new("1+2*3");
+    let json = tree.to_json();
+    assert_eq!(
+        json,
+        r#"{"left":{"value":"1"},"op":"+","right":{"left":{"value":"2"},"op":"*","right":{"value":"3"}}}"#
+    );
+    let read = Tree::from_json(&json).unwrap();
+    assert_eq!(read.fingerprint(), tree.fingerprint());
+    assert_eq!(read.to_json(), json);
+    assert_eq!(read.evaluate(), Value::from(7));
+
+    for s in &["-x // 7/2 % 3", "|x - 1| ^ 2", "x ? 1 : y ? 2 : 3"] {
+        let tree = Tree::new(s);
+        assert_eq!(
+            Tree::from_json(&tree.to_json()).map(|read| read.fingerprint()),
+            Ok(tree.fingerprint())
+        );
+    }
+
+    let invalid = |s: &str| matches!(Tree::from_json(s), Err(EvalError::InvalidJson(_)));
+    assert!(invalid("[1, 2]"));
+    assert!(invalid(r#"{"op":"+","left":{"value":"1"}}"#));
+    assert!(invalid(
+        r#"{"op":"+=","left":{"value":"1"},"right":{"value":"1"}}"#
+    ));
+    assert!(invalid(r#"{"value":"1","right":{"value":"1"}}"#));
+    assert!(invalid(
+        r#"{"op":":","left":{"value":"1"},"right":{"value":"2"}}"#
+    ));
+    assert!(invalid(r#"{"variable":7}"#));
+    assert_eq!(
+        Tree::from_json(r#"{"value":"1/0"}"#).err(),
+        Some(EvalError::Lex(LexError::InvalidNumber("1/0".to_string())))
+    );
+}
+
+#[test]
+fn test_tree_locale() {
+    let comma = ParseOptions {
+        locale: LocaleOptions {
+            decimal_comma: true,
+        },
+        ..ParseOptions::default()
+    };
+    let evaluate = |s: &str, options: ParseOptions| Tree::new_with_options(s, options).evaluate();
+
+    assert_eq!(evaluate("3,5 + 1,5", comma), Value::from(5));
+    assert_eq!(evaluate("3,5", comma), Value::from(3.5));
+    assert_eq!(evaluate("3,5", ParseOptions::default()), Value::from(35));
+    assert_eq!(evaluate("1.250,5 * 2", comma), Value::from(2501));
+
+    let fractions_only = ParseOptions {
+        fractions_only: true,
+        ..comma
+    };
+    assert_eq!(
+        Tree::try_new_with_options("1.000 + 0,5", fractions_only).err(),
+        Some(LexError::DecimalNotAllowed(9).into())
+    );
+}
+
+#[test]
+fn test_tree_evaluate_integer() {
+    let evaluate = |s: &str| Tree::new(s).evaluate_integer();
+
+    assert_eq!(evaluate("6/2+1"), Ok(4));
+    assert_eq!(evaluate("7 // 2 + 7 % 2"), Ok(4));
+    assert_eq!(evaluate("-2^3 * |1 - 4|"), Ok(-24));
+    assert_eq!(evaluate("1 ? 8/4 : 7/2"), Ok(2));
+    assert_eq!(evaluate("7/2"), Err(EvalError::NonIntegerResult));
+    // the remainder is an error even though the product is whole
+    assert_eq!(evaluate("7/2*2"), Err(EvalError::NonIntegerResult));
+    assert_eq!(evaluate("0.5 + 1/2"), Err(EvalError::NonIntegerResult));
+    assert_eq!(evaluate("2^-1"), Err(EvalError::NonIntegerResult));
+    assert_eq!(evaluate("1/0"), Err(EvalError::DivideByZero));
+    assert_eq!(
+        evaluate("x + 1"),
+        Err(EvalError::UnknownVariable("x".to_string()))
+    );
+}
+
+#[test]
+fn test_tree_partial_results() {
+    let partial_results = |s: &str| Tree::new(s).partial_results();
+    let values = |values: &[i64]| Ok(values.iter().copied().map(Value::from).collect::<Vec<_>>());
+
+    assert_eq!(partial_results("1+2+3+4"), values(&[1, 3, 6, 10]));
+    assert_eq!(partial_results("2*3*4"), values(&[2, 6, 24]));
+    assert_eq!(partial_results("10-1-2"), values(&[10, 9, 7]));
+    // operands are evaluated whole, only the root chain is split up
+    assert_eq!(partial_results("1+2*3+(4+5)"), values(&[1, 7, 16]));
+    // `-` ends a chain of `+`
+    assert_eq!(partial_results("1-2+3+4"), values(&[-1, 2, 6]));
+    assert_eq!(partial_results("2^3^2"), values(&[512]));
+    assert_eq!(partial_results("7"), values(&[7]));
+
+    assert_eq!(partial_results("1+2+1/0"), Err(EvalError::DivideByZero));
+    assert_eq!(
+        partial_results("9223372036854775807 + 1 - 1"),
+        Err(EvalError::Overflow)
+    );
+}
+
+#[test]
+fn test_tree_integer_overflow() {
+    let evaluate = |s: &str| Tree::new(s).evaluate_with(&HashMap::new());
+
+    assert_eq!(
+        evaluate("9223372036854775807 + 1"),
+        Err(EvalError::Overflow)
+    );
+    assert_eq!(
+        evaluate("-9223372036854775807 - 2"),
+        Err(EvalError::Overflow)
+    );
+    assert_eq!(
+        evaluate("9223372036854775807 + 1 - 1"),
+        Err(EvalError::Overflow)
+    );
+    assert_eq!(
+        evaluate("9223372036854775807 - 1 + 1"),
+        Ok(Value::from(i64::MAX))
+    );
+}
+
+#[test]
+fn test_tree_constants() {
+    assert!(evaluate("tau").approx_eq_f64(std::f64::consts::TAU, 1e-11));
+    assert!(evaluate("tau - 2*pi").approx_eq_f64(0.0, 1e-11));
+    assert!(evaluate("phi").approx_eq_f64(1.618, 1e-3));
+    // the defining property of the golden ratio
+    assert!(evaluate("phi^2 - phi - 1").approx_eq_f64(0.0, 1e-11));
+    assert!(evaluate("e").approx_eq_f64(std::f64::consts::E, 1e-11));
+
+    // a bound variable shadows the constant
+    let mut vars = HashMap::new();
+    vars.insert("e".to_string(), Value::from(3));
+    assert_eq!(Tree::new("e + 1").evaluate_with(&vars), Ok(Value::from(4)));
+
+    assert_eq!(
+        Tree::new("2 * pi").evaluate_integer(),
+        Err(EvalError::NonIntegerResult)
+    );
+}
+
+#[test]
+fn test_tree_evaluate_detailed() {
+    assert_eq!(
+        Tree::new("7/2 + 1").evaluate_detailed(),
+        Ok(EvalResult {
+            value: fraction(9, 2),
+            operations: 2,
+            inexact_division: true,
+            float_fallback: false,
+        })
+    );
+
+    let exact = Tree::new("|-8/4| ? 2 * 3 : 1").evaluate_detailed().unwrap();
+    assert_eq!(exact.value, Value::from(6));
+    assert_eq!(exact.operations, 5);
+    assert!(!exact.inexact_division);
+    // the branch not taken isn't evaluated
+    assert!(
+        !Tree::new("1 ? 2 : 1/3")
+            .evaluate_detailed()
+            .unwrap()
+            .inexact_division
+    );
+    assert!(
+        Tree::new("(7/4) * 4")
+            .evaluate_detailed()
+            .unwrap()
+            .inexact_division
+    );
+
+    let lossy = Tree::new("2^62 * 4 / 2^61").evaluate_detailed().unwrap();
+    assert_eq!(lossy.value, Value::from(8));
+    assert!(lossy.float_fallback);
+
+    assert_eq!(
+        Tree::new("1/0").evaluate_detailed(),
+        Err(EvalError::DivideByZero)
+    );
+}
+
+#[test]
+fn test_tree_evaluate_over() {
+    let values = |values: &[i64]| values.iter().copied().map(Value::from).collect::<Vec<_>>();
+
+    assert_eq!(
+        Tree::new("x*x").evaluate_over('x', &values(&[1, 2, 3])),
+        Ok(values(&[1, 4, 9]))
+    );
+    assert_eq!(
+        Tree::new("1/t").evaluate_over('t', &values(&[2, 4])),
+        Ok(vec![Value::from(0.5), Value::from(0.25)])
+    );
+    assert_eq!(Tree::new("x + 1").evaluate_over('x', &[]), Ok(vec![]));
+    assert_eq!(
+        Tree::new("1/x").evaluate_over('x', &values(&[1, 0, -1])),
+        Err(EvalError::DivideByZero)
+    );
+    assert_eq!(
+        Tree::new("x + y").evaluate_over('x', &values(&[1])),
+        Err(EvalError::UnknownVariable("y".to_string()))
+    );
+}
+
+#[test]
+fn test_tree_operator_functions() {
+    assert_eq!(evaluate("add(2,3)"), Value::from(5));
+    assert_eq!(evaluate("div(1,4)"), fraction(1, 4));
+    assert_eq!(evaluate("sub(1, 2 + 3)"), Value::from(-4));
+    // the arguments group, `mul(1 + 1, 3)` isn't `1 + 1 * 3`
+    assert_eq!(evaluate("mul(1 + 1, 3)"), Value::from(6));
+    assert_eq!(evaluate("pow(2, pow(3, 2))"), Value::from(512));
+    assert_eq!(evaluate("2 ^ add(1, 1) * 3"), Value::from(12));
+
+    assert_eq!(
+        Tree::try_new("add(1)").err(),
+        Some(LexError::ExpectedTwoArguments(0).into())
+    );
+    assert_eq!(
+        Tree::try_new("add(1, 2").err(),
+        Some(EvalError::UnbalancedParens)
+    );
+}
+
+#[test]
+fn test_tree_repeating_decimal() {
+    assert_eq!(Tree::new("0.(3)").evaluate(), fraction(1, 3));
+    assert_eq!(Tree::new("0.1(6)").evaluate(), fraction(1, 6));
+    assert_eq!(Tree::new("3 * 0.(3)").evaluate(), Value::from(1));
+    assert_eq!(Tree::new("0.(6) - 0.(3)").evaluate(), fraction(1, 3));
+}
+
+#[test]
+fn test_tree_negated_function() {
+    let mut functions = FunctionRegistry::new();
+    functions.insert("sqrt".to_string(), |v| {
+        if v < Value::from(0) {
+            return Err(EvalError::OutOfDomain);
+        }
+        Value::checked_from_f64(v.to_f64().sqrt()).ok_or(EvalError::Overflow)
+    });
+    let evaluate = |s: &str| Tree::new(s).evaluate_with_functions(&functions);
+
+    assert_eq!(evaluate("-sqrt(4)"), Ok(Value::from(-2)));
+    assert_eq!(evaluate("3 - sqrt(4)"), Ok(Value::from(1)));
+    assert_eq!(evaluate("3 - -sqrt(4)"), Ok(Value::from(5)));
+    assert_eq!(evaluate("-sqrt(4) * 3"), Ok(Value::from(-6)));
+    assert_eq!(evaluate("-sqrt(4) + sqrt(9)"), Ok(Value::from(1)));
+    assert_eq!(evaluate("2 * -sqrt(16) / 4"), Ok(Value::from(-2)));
+    assert_eq!(evaluate("- - sqrt(4)"), Ok(Value::from(2)));
+    assert_eq!(evaluate("-|-3|"), Ok(Value::from(-3)));
+    // the minus applies to the result, not the argument
+    assert_eq!(evaluate("-sqrt(-4)"), Err(EvalError::OutOfDomain));
+    assert_eq!(evaluate("sqrt(-4)"), Err(EvalError::OutOfDomain));
+}
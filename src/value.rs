@@ -1,8 +1,78 @@
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::{
+    cmp::Ordering,
+    convert::TryFrom,
+    hash::{Hash, Hasher},
+    iter::{Product, Sum},
+    ops::{Add, Div, Mul, Neg, Sub},
+};
 
-use num::integer::{gcd, lcm};
+use num::{integer::gcd, rational::Rational64, BigInt, BigRational, Signed, ToPrimitive};
 
-#[derive(PartialEq, Debug, Clone, Copy)]
+use crate::config::{config, DisplayStyle, OverflowMode};
+use crate::{EvalError, LexError};
+
+/// The `(numerator, denominator, glyph)` of the vulgar fractions common enough to be in most fonts
+const VULGAR_FRACTIONS: &[(i64, i64, char)] = &[
+    (1, 2, '½'),
+    (1, 3, '⅓'),
+    (2, 3, '⅔'),
+    (1, 4, '¼'),
+    (3, 4, '¾'),
+    (1, 5, '⅕'),
+    (2, 5, '⅖'),
+    (3, 5, '⅗'),
+    (4, 5, '⅘'),
+    (1, 6, '⅙'),
+    (5, 6, '⅚'),
+    (1, 8, '⅛'),
+    (3, 8, '⅜'),
+    (5, 8, '⅝'),
+    (7, 8, '⅞'),
+];
+
+/// Which sign the result of a modulo takes
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Modulo {
+    /// Follows the sign of the divisor, `-7 % 3 == 2`
+    #[default]
+    Floored,
+    /// Follows the sign of the dividend (like Rust's `%`), `-7 % 3 == -1`
+    Truncated,
+}
+
+/// Which way a value that falls between two floats is rounded
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum RoundingMode {
+    /// The closer float, ties go to the one with an even mantissa
+    #[default]
+    Nearest,
+    TowardZero,
+    /// Toward positive infinity
+    Up,
+    /// Toward negative infinity
+    Down,
+}
+
+/// A reduced fraction with a positive denominator, `Value::as_fraction` writes the non-finite values
+/// as `1/0`, `-1/0` and `0/0`
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub struct Fraction {
+    pub numerator: i64,
+    pub denominator: i64,
+}
+impl std::fmt::Display for Fraction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.denominator == 1 {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+/// Values are equal, and hash the same, when they're the same number however their fields are
+/// written, `Rational { quotient: 0, remainder: 2, divisor: 4 }` is `1/2`
+#[derive(Debug, Clone, Copy)]
 pub enum Value {
     Integer(i64),
     Rational {
@@ -10,337 +80,2475 @@ pub enum Value {
         remainder: i64,
         divisor: i64,
     },
+    /// The result of dividing a positive value by zero, only produced when evaluating with
+    /// `EvalMode::non_finite`
+    Infinity,
+    /// The result of dividing a negative value by zero
+    NegInfinity,
+    /// The result of `0/0` and of operations like `Infinity - Infinity`. It propagates through
+    /// arithmetic like a float NaN, but unlike one it's equal to itself (so results
+    /// can be compared against it). It's unordered against everything, `partial_cmp` is `None`
+    Undefined,
 }
 impl Value {
-    pub fn simplify(self) -> Self {
-        #[cfg(test)]
-        println!("Simplifying: {:#?}", self);
-        match self {
-            Self::Integer(_) => self,
-            Self::Rational {
-                mut quotient,
-                mut remainder,
-                mut divisor,
-            } => {
-                let common = gcd(remainder, divisor);
-                if common != divisor {
-                    remainder /= common;
-                    divisor /= common;
-                }
+    pub fn zero() -> Self {
+        Self::Integer(0)
+    }
 
-                if remainder / divisor != 0 {
-                    quotient += remainder / divisor;
-                    remainder -= divisor * (remainder / divisor);
-                }
+    pub fn one() -> Self {
+        Self::Integer(1)
+    }
 
-                if remainder != 0 {
-                    #[cfg(test)]
-                    println!(
-                        "Result: {:#?}",
-                        Self::Rational {
-                            quotient,
-                            remainder,
-                            divisor,
-                        }
-                    );
-                    Self::Rational {
-                        quotient,
-                        remainder,
-                        divisor,
-                    }
-                } else {
-                    #[cfg(test)]
-                    println!("Result: {:#?}", Self::Integer(quotient));
-                    Self::Integer(quotient)
-                }
-            }
+    /// `numerator / denominator` in lowest terms with the sign on the numerator, this is the way to
+    /// build a fraction without going through the `Rational` fields
+    pub fn new_rational(numerator: i64, denominator: i64) -> Result<Self, EvalError> {
+        if denominator == 0 {
+            return Err(EvalError::DivideByZero);
         }
+        Self::checked_from_ratio(numerator, denominator).ok_or(EvalError::Overflow)
     }
-}
-impl PartialEq<i64> for Value {
-    fn eq(&self, other: &i64) -> bool {
-        match self {
-            Self::Integer(i) => i == other,
-            Self::Rational { .. } => false,
+
+    /// A single number written as an integer, a decimal, an `n/d` fraction or an `x%` percentage, as
+    /// its exact reduced value. Unlike `Value::try_from` nothing is evaluated, so `2+2` is rejected
+    /// ```rust
+    /// # use rational_calculator::Value;
+    /// assert_eq!(Value::parse_literal("12.5%").ok(), Value::new_rational(1, 8).ok());
+    /// ```
+    pub fn parse_literal(s: &str) -> Result<Self, LexError> {
+        let literal = s.trim();
+        let invalid = || LexError::InvalidNumber(literal.to_string());
+        let (number, scale) = match literal.strip_suffix('%') {
+            Some(number) => (number.trim_end(), 100),
+            None => (literal, 1),
+        };
+        let (numerator, denominator) = match number.split_once('/') {
+            Some((numerator, denominator)) => (
+                numerator.trim().parse().map_err(|_| invalid())?,
+                denominator.trim().parse().map_err(|_| invalid())?,
+            ),
+            None => Self::decimal_ratio(number).ok_or_else(invalid)?,
+        };
+        denominator
+            .checked_mul(scale)
+            .and_then(|denominator| Self::checked_from_ratio(numerator, denominator))
+            .ok_or_else(invalid)
+    }
+
+    /// The unreduced `(numerator, denominator)` of a base 10 literal like `-12.34`, read digit for
+    /// digit rather than through `f64`
+    fn decimal_ratio(s: &str) -> Option<(i64, i64)> {
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
         }
+        let numerator = format!("{}{}", whole, fraction).parse().ok()?;
+        let denominator = 10i64.checked_pow(u32::try_from(fraction.len()).ok()?)?;
+        Some((numerator, denominator))
     }
-}
-impl From<i64> for Value {
-    fn from(i: i64) -> Self {
-        Self::Integer(i)
+
+    /// Build the reduced form of `numerator / denominator`
+    fn from_ratio(numerator: i64, denominator: i64) -> Self {
+        if denominator == 0 {
+            panic!("Divide by zero");
+        }
+        Self::checked_from_ratio(numerator, denominator).expect("Overflow")
     }
-}
-impl From<f64> for Value {
-    fn from(f: f64) -> Self {
-        match fraction::GenericFraction::<i64>::from(f) {
-            fraction::GenericFraction::Rational(_, ratio) => Self::Rational {
-                quotient: 0,
-                remainder: *ratio.numer(),
-                divisor: *ratio.denom(),
-            },
-            _ => panic!(),
+
+    /// Build the reduced form of `numerator / denominator`, `None` if the denominator is zero
+    /// or the reduced form doesn't fit
+    fn checked_from_ratio(numerator: i64, denominator: i64) -> Option<Self> {
+        if denominator == 0 {
+            return None;
+        }
+
+        let common = checked_gcd(numerator, denominator)?.checked_mul(denominator.signum())?;
+        let (numerator, denominator) = (
+            numerator.checked_div(common)?,
+            denominator.checked_div(common)?,
+        );
+
+        if denominator == 1 {
+            Some(Self::Integer(numerator))
+        } else {
+            Some(Self::Rational {
+                quotient: numerator / denominator,
+                remainder: numerator % denominator,
+                divisor: denominator,
+            })
         }
     }
-}
-impl Add for Value {
-    type Output = Self;
 
-    fn add(self, rhs: Self) -> Self {
-        match (self, rhs) {
-            (Self::Integer(lhs), Self::Integer(rhs)) => Self::Integer(lhs + rhs),
-            (
-                Self::Rational {
-                    quotient,
-                    remainder,
-                    divisor,
-                },
-                Self::Integer(rhs),
-            )
-            | (
-                Self::Integer(rhs),
-                Self::Rational {
-                    quotient,
-                    remainder,
-                    divisor,
-                },
-            ) => Self::Rational {
-                quotient: quotient + rhs,
+    /// The value as an improper `(numerator, denominator)` pair
+    fn to_ratio(self) -> (i64, i64) {
+        if !self.is_finite() {
+            panic!("{} has no ratio", self);
+        }
+        self.checked_to_ratio().expect("Overflow")
+    }
+
+    fn checked_to_ratio(self) -> Option<(i64, i64)> {
+        match self {
+            Self::Integer(i) => Some((i, 1)),
+            Self::Rational { divisor: 0, .. } => None,
+            Self::Infinity | Self::NegInfinity | Self::Undefined => None,
+            Self::Rational {
+                quotient,
                 remainder,
                 divisor,
-            },
-            (
-                Self::Rational {
-                    quotient: lhs_quotient,
-                    remainder: lhs_remainder,
-                    divisor: lhs_divisor,
-                },
-                Self::Rational {
-                    quotient: rhs_quotient,
-                    remainder: rhs_remainder,
-                    divisor: rhs_divisor,
-                },
-            ) => {
-                let divisor = lcm(lhs_divisor, rhs_divisor);
-                let quotient = lhs_quotient + rhs_quotient;
-                let remainder = (lhs_remainder * divisor.checked_div(lhs_divisor).unwrap_or(1))
-                    + (rhs_remainder * divisor.checked_div(rhs_divisor).unwrap_or(1));
+            } => Some((
+                quotient.checked_mul(divisor)?.checked_add(remainder)?,
+                divisor,
+            )),
+        }
+    }
 
-                Self::Rational {
-                    quotient,
-                    remainder,
-                    divisor,
-                }
-                .simplify()
+    pub(crate) fn checked_from_f64(f: f64) -> Option<Self> {
+        match fraction::GenericFraction::<i64>::from(f) {
+            fraction::GenericFraction::Rational(sign, ratio) => {
+                let numerator = match sign {
+                    fraction::Sign::Plus => *ratio.numer(),
+                    fraction::Sign::Minus => ratio.numer().checked_neg()?,
+                };
+                Self::checked_from_ratio(numerator, *ratio.denom())
             }
+            _ => None,
         }
     }
-}
-impl Sub for Value {
-    type Output = Self;
-    fn sub(self, rhs: Self) -> Self {
-        match (self, rhs) {
-            (Self::Integer(lhs), Self::Integer(rhs)) => Self::Integer(lhs - rhs),
-            (
-                Self::Rational {
-                    quotient,
-                    remainder,
-                    divisor,
-                },
-                Self::Integer(rhs),
-            )
-            | (
-                Self::Integer(rhs),
-                Self::Rational {
-                    quotient,
-                    remainder,
-                    divisor,
-                },
-            ) => Self::Rational {
-                quotient: quotient - rhs,
-                remainder,
-                divisor,
-            },
-            (
-                Self::Rational {
-                    quotient: lhs_quotient,
-                    remainder: lhs_remainder,
-                    divisor: lhs_divisor,
-                },
-                Self::Rational {
-                    quotient: rhs_quotient,
-                    remainder: rhs_remainder,
-                    divisor: rhs_divisor,
-                },
-            ) => {
-                let divisor = lcm(lhs_divisor, rhs_divisor);
-                let quotient = lhs_quotient - rhs_quotient;
-                let remainder = (lhs_remainder * divisor.checked_div(lhs_divisor).unwrap_or(1))
-                    - (rhs_remainder * divisor.checked_div(rhs_divisor).unwrap_or(1));
 
-                Self::Rational {
-                    quotient,
-                    remainder,
-                    divisor,
-                }
-                .simplify()
-            }
+    #[cfg(feature = "decimal")]
+    pub(crate) fn checked_from_decimal(d: rust_decimal::Decimal) -> Option<Self> {
+        let numerator = i64::try_from(d.mantissa()).ok()?;
+        let denominator = 10i64.checked_pow(d.scale())?;
+        Self::checked_from_ratio(numerator, denominator)
+    }
+
+    /// Whether the value is a number rather than one of the `Infinity`, `NegInfinity` or `Undefined`
+    /// sentinels
+    pub fn is_finite(&self) -> bool {
+        !matches!(self, Self::Infinity | Self::NegInfinity | Self::Undefined)
+    }
+
+    /// `-1`, `0` or `1` by the sign of the value, `None` for `Undefined`
+    fn sign(self) -> Option<i64> {
+        match self {
+            Self::Infinity => Some(1),
+            Self::NegInfinity => Some(-1),
+            Self::Undefined => None,
+            _ => Some(self.checked_to_ratio()?.0.signum()),
         }
     }
-}
-impl Mul for Value {
-    type Output = Self;
-    fn mul(self, rhs: Self) -> Self {
-        match (self, rhs) {
-            (Self::Integer(lhs), Self::Integer(rhs)) => Self::Integer(lhs * rhs),
-            (
-                Self::Rational {
-                    quotient,
-                    remainder,
-                    divisor,
-                },
-                Self::Integer(rhs),
-            )
-            | (
-                Self::Integer(rhs),
-                Self::Rational {
-                    quotient,
-                    remainder,
-                    divisor,
-                },
-            ) => {
-                Self::Rational {
-                    quotient,
-                    remainder,
-                    divisor,
-                } * Self::Rational {
-                    quotient: rhs,
-                    remainder: 0,
-                    divisor: 1,
-                }
-            }
-            (
-                Self::Rational {
-                    quotient: lhs_quotient,
-                    remainder: lhs_remainder,
-                    divisor: lhs_divisor,
-                },
-                Self::Rational {
-                    quotient: rhs_quotient,
-                    remainder: rhs_remainder,
-                    divisor: rhs_divisor,
-                },
-            ) => {
-                let remainder = ((lhs_quotient * lhs_divisor) + lhs_remainder)
-                    * ((rhs_quotient * rhs_divisor) + rhs_remainder);
-                let divisor = lhs_divisor * rhs_divisor;
 
-                Self::Rational {
-                    quotient: 0,
-                    remainder,
-                    divisor,
-                }
-                .simplify()
-            }
+    /// `x / 0` under IEEE float rules, an infinity with the sign of `self`, or `Undefined` for `0/0`
+    pub fn divide_by_zero(self) -> Self {
+        match self.sign() {
+            Some(1) => Self::Infinity,
+            Some(-1) => Self::NegInfinity,
+            _ => Self::Undefined,
         }
     }
-}
-impl Div for Value {
-    type Output = Self;
-    fn div(self, rhs: Self) -> Self {
+
+    /// `self + rhs`, `None` on overflow
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
         match (self, rhs) {
-            (Self::Integer(lhs), Self::Integer(rhs)) => Self::Rational {
-                quotient: 0,
-                remainder: lhs,
-                divisor: rhs,
-            }
-            .simplify(),
-            (
-                Self::Rational {
-                    quotient,
-                    remainder,
-                    divisor,
-                },
-                Self::Integer(rhs),
-            )
-            | (
-                Self::Integer(rhs),
-                Self::Rational {
-                    quotient,
-                    remainder,
-                    divisor,
-                },
-            ) => {
-                Self::Rational {
-                    quotient,
-                    remainder,
-                    divisor,
-                } / Self::Rational {
-                    quotient: rhs,
-                    remainder: 0,
-                    divisor: 1,
-                }
-            }
-            (
-                Self::Rational {
-                    quotient: lhs_quotient,
-                    remainder: lhs_remainder,
-                    divisor: lhs_divisor,
-                },
-                Self::Rational {
-                    quotient: rhs_quotient,
-                    remainder: rhs_remainder,
-                    divisor: rhs_divisor,
-                },
-            ) => {
-                let remainder = ((lhs_quotient * lhs_divisor) + lhs_remainder) * rhs_divisor;
-                let divisor = lhs_divisor * ((rhs_quotient * rhs_divisor) + rhs_remainder);
+            (Self::Undefined, _)
+            | (_, Self::Undefined)
+            | (Self::Infinity, Self::NegInfinity)
+            | (Self::NegInfinity, Self::Infinity) => return Some(Self::Undefined),
+            (Self::Infinity, _) | (Self::NegInfinity, _) => return Some(self),
+            (_, Self::Infinity) | (_, Self::NegInfinity) => return Some(rhs),
+            _ => (),
+        }
 
-                Self::Rational {
-                    quotient: 0,
-                    remainder,
-                    divisor,
+        let (lhs_numerator, lhs_denominator) = self.checked_to_ratio()?;
+        let (rhs_numerator, rhs_denominator) = rhs.checked_to_ratio()?;
+
+        let common = checked_gcd(lhs_denominator, rhs_denominator)?;
+        let denominator = (lhs_denominator / common).checked_mul(rhs_denominator)?;
+        let numerator = lhs_numerator
+            .checked_mul(denominator / lhs_denominator)?
+            .checked_add(rhs_numerator.checked_mul(denominator / rhs_denominator)?)?;
+
+        Self::checked_from_ratio(numerator, denominator)
+    }
+
+    /// `self - rhs`, `None` on overflow
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.checked_add(rhs.checked_neg()?)
+    }
+
+    /// `self * rhs`, `None` on overflow
+    pub fn checked_mul(self, rhs: Self) -> Option<Self> {
+        if !self.is_finite() || !rhs.is_finite() {
+            return Some(match (self.sign(), rhs.sign()) {
+                (Some(lhs), Some(rhs)) if lhs * rhs != 0 => {
+                    Self::Integer(lhs * rhs).divide_by_zero()
                 }
-                .simplify()
-            }
+                // an infinity times zero has no sign to take
+                _ => Self::Undefined,
+            });
         }
+
+        let (lhs_numerator, lhs_denominator) = self.checked_to_ratio()?;
+        let (rhs_numerator, rhs_denominator) = rhs.checked_to_ratio()?;
+
+        // cancel across before multiplying to keep the intermediates small
+        let lhs_common = checked_gcd(lhs_numerator, rhs_denominator)?;
+        let rhs_common = checked_gcd(rhs_numerator, lhs_denominator)?;
+        let numerator = (lhs_numerator / lhs_common).checked_mul(rhs_numerator / rhs_common)?;
+        let denominator =
+            (lhs_denominator / rhs_common).checked_mul(rhs_denominator / lhs_common)?;
+
+        Self::checked_from_ratio(numerator, denominator)
     }
-}
-impl Neg for Value {
-    type Output = Self;
 
-    fn neg(self) -> Self::Output {
+    /// `self / rhs`, `None` on overflow or if `rhs` is zero
+    pub fn checked_div(self, rhs: Self) -> Option<Self> {
+        self.checked_mul(rhs.recip_checked()?)
+    }
+
+    /// `1 / self`, `None` if the value is zero or the reciprocal doesn't fit (`1 / i64::MIN`)
+    pub fn recip_checked(self) -> Option<Self> {
         match self {
-            Self::Integer(i) => Self::Integer(-i),
-            Self::Rational {
-                mut quotient,
-                remainder,
-                divisor,
-            } => {
-                if quotient == 0 {
-                    quotient = -1;
-                } else {
-                    quotient = -quotient;
-                }
-                Self::Rational {
-                    quotient,
-                    remainder,
-                    divisor,
-                }
-            }
+            Self::Infinity | Self::NegInfinity => return Some(Self::Integer(0)),
+            Self::Undefined => return Some(self),
+            _ => (),
         }
+        let (numerator, denominator) = self.checked_to_ratio()?;
+        Self::checked_from_ratio(denominator, numerator)
     }
-}
-impl std::fmt::Display for Value {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+
+    /// `-self`, `None` on overflow
+    pub fn checked_neg(self) -> Option<Self> {
         match self {
-            Self::Integer(i) => write!(f, "{}", i),
-            Self::Rational {
-                quotient,
-                remainder,
-                divisor,
-            } => {
-                write!(f, "{} ({} / {})", quotient, remainder, divisor)
+            Self::Infinity => return Some(Self::NegInfinity),
+            Self::NegInfinity => return Some(Self::Infinity),
+            Self::Undefined => return Some(self),
+            _ => (),
+        }
+        let (numerator, denominator) = self.checked_to_ratio()?;
+        Self::checked_from_ratio(numerator.checked_neg()?, denominator)
+    }
+
+    /// `|self|`, `None` on overflow (`|i64::MIN|`)
+    pub fn checked_abs(self) -> Option<Self> {
+        if self.sign() == Some(-1) {
+            self.checked_neg()
+        } else {
+            Some(self)
+        }
+    }
+
+    /// `self + rhs`, reporting overflow as an error
+    pub fn try_add(self, rhs: Self) -> Result<Self, EvalError> {
+        self.checked_add(rhs).ok_or(EvalError::Overflow)
+    }
+
+    /// `self - rhs`, reporting overflow as an error
+    pub fn try_sub(self, rhs: Self) -> Result<Self, EvalError> {
+        self.checked_sub(rhs).ok_or(EvalError::Overflow)
+    }
+
+    /// `self * rhs`, reporting overflow as an error
+    pub fn try_mul(self, rhs: Self) -> Result<Self, EvalError> {
+        self.checked_mul(rhs).ok_or(EvalError::Overflow)
+    }
+
+    /// `self / rhs`, reporting overflow and division by zero as errors
+    pub fn try_div(self, rhs: Self) -> Result<Self, EvalError> {
+        if rhs == 0 {
+            return Err(EvalError::DivideByZero);
+        }
+        self.checked_div(rhs).ok_or(EvalError::Overflow)
+    }
+
+    /// `self / rhs` for contexts that need a whole result, erroring if the quotient isn't an integer
+    pub fn divide_exact(self, rhs: Self) -> Result<Self, EvalError> {
+        match self.try_div(rhs)? {
+            quotient @ Self::Integer(_) => Ok(quotient),
+            _ => Err(EvalError::NotAnInteger),
+        }
+    }
+
+    /// `self * a + b` as a single exact step, so only the result has to fit rather than the product.
+    /// The steps are in `i128`, only denominators too large to share factors can outgrow it
+    pub fn mul_add(self, a: Value, b: Value) -> Value {
+        if !self.is_finite() || !a.is_finite() || !b.is_finite() {
+            return self * a + b;
+        }
+
+        let ((lhs_numerator, lhs_denominator), (rhs_numerator, rhs_denominator)) =
+            (self.to_ratio(), a.to_ratio());
+        let (b_numerator, b_denominator) = b.to_ratio();
+        // cancel across the product first, so it's in lowest terms and at most 126 bits
+        let (cross_lhs, cross_rhs) = (
+            gcd(lhs_numerator, rhs_denominator),
+            gcd(rhs_numerator, lhs_denominator),
+        );
+        let product_numerator =
+            i128::from(lhs_numerator / cross_lhs) * i128::from(rhs_numerator / cross_rhs);
+        let product_denominator =
+            i128::from(lhs_denominator / cross_rhs) * i128::from(rhs_denominator / cross_lhs);
+
+        let common = gcd(product_denominator, i128::from(b_denominator));
+        let sum = product_numerator
+            .checked_mul(i128::from(b_denominator) / common)
+            .zip(i128::from(b_numerator).checked_mul(product_denominator / common))
+            .and_then(|(lhs, rhs)| lhs.checked_add(rhs))
+            .zip((product_denominator / common).checked_mul(i128::from(b_denominator)));
+        // the sign to saturate to, the product's when even the `i128` sum overflowed
+        let negative = match sum {
+            Some((numerator, _)) => numerator < 0,
+            None => (product_numerator < 0) != (product_denominator < 0),
+        };
+        sum.and_then(|(numerator, denominator)| {
+            let divisor = gcd(numerator, denominator) * denominator.signum();
+            Self::checked_from_ratio(
+                i64::try_from(numerator / divisor).ok()?,
+                i64::try_from(denominator / divisor).ok()?,
+            )
+        })
+        .unwrap_or_else(|| overflowed(EvalError::Overflow, negative))
+    }
+
+    /// `self ^ exponent` by repeated multiplication, `None` on overflow
+    pub fn checked_pow(self, exponent: u32) -> Option<Self> {
+        let (mut result, mut base, mut exponent) = (Self::Integer(1), self, exponent);
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.checked_mul(base)?;
+            }
+            exponent >>= 1;
+            // squaring past the last bit could overflow on a result that fits
+            if exponent > 0 {
+                base = base.checked_mul(base)?;
             }
         }
+        Some(result)
+    }
+
+    /// `self ^ exponent`, `None` on overflow or for a negative power of zero
+    pub(crate) fn checked_powi(self, exponent: i64) -> Option<Self> {
+        let base = if exponent < 0 {
+            Self::Integer(1).checked_div(self)?
+        } else {
+            self
+        };
+        match u32::try_from(exponent.unsigned_abs()) {
+            Ok(exponent) => base.checked_pow(exponent),
+            // past `u32::MAX` only 0 and ±1 have a power that fits
+            Err(_) if base == 0 || base == 1 => Some(base),
+            Err(_) if base == -1 => Some(Self::Integer(if exponent % 2 == 0 { 1 } else { -1 })),
+            Err(_) => None,
+        }
     }
+
+    /// `self / rhs` rounded toward negative infinity, `None` on overflow or if `rhs` is zero
+    pub(crate) fn checked_div_floor(self, rhs: Self) -> Option<Self> {
+        if !self.is_finite() || !rhs.is_finite() {
+            // a finite value over an infinity is already a whole 0
+            return self.checked_div(rhs);
+        }
+        let (lhs_numerator, lhs_denominator) = self.checked_to_ratio()?;
+        let (rhs_numerator, rhs_denominator) = rhs.checked_to_ratio()?;
+
+        let numerator = lhs_numerator.checked_mul(rhs_denominator)?;
+        let denominator = lhs_denominator.checked_mul(rhs_numerator)?;
+        let quotient = numerator.checked_div(denominator)?;
+        if numerator.checked_rem(denominator)? != 0 && (numerator < 0) != (denominator < 0) {
+            Some(Self::Integer(quotient - 1))
+        } else {
+            Some(Self::Integer(quotient))
+        }
+    }
+
+    /// The remainder of `self / rhs`, `None` on overflow or if `rhs` is zero
+    pub(crate) fn checked_rem(self, rhs: Self, convention: Modulo) -> Option<Self> {
+        // like `fmod`, an infinite divisor leaves the dividend as it is
+        match (self, rhs) {
+            (_, Self::Infinity) | (_, Self::NegInfinity) if self.is_finite() => return Some(self),
+            _ if !self.is_finite() || !rhs.is_finite() => return Some(Self::Undefined),
+            _ => (),
+        }
+        let quotient = match convention {
+            Modulo::Floored => self.checked_div_floor(rhs)?,
+            Modulo::Truncated => {
+                let (numerator, denominator) = self.checked_div(rhs)?.checked_to_ratio()?;
+                Self::Integer(numerator / denominator)
+            }
+        };
+
+        self.checked_sub(rhs.checked_mul(quotient)?)
+    }
+
+    /// The closest value with a denominator of at most `max`, found from the continued fraction
+    /// of the value. Values that already fit are returned as they are
+    pub fn limit_denominator(self, max: i64) -> Value {
+        if max < 1 {
+            panic!("The denominator limit must be positive, got {}", max);
+        }
+        if !self.is_finite() {
+            return self;
+        }
+        let (numerator, denominator) = self.simplify().to_ratio();
+        if denominator <= max {
+            return self;
+        }
+
+        // (p0 / q0, p1 / q1) are the last two convergents with a denominator under the limit
+        let max = i128::from(max);
+        let (mut p0, mut q0, mut p1, mut q1) = (0i128, 1i128, 1i128, 0i128);
+        let (mut n, mut d) = (i128::from(numerator), i128::from(denominator));
+        loop {
+            let a = n.div_euclid(d);
+            let q2 = q0 + a * q1;
+            if q2 > max {
+                break;
+            }
+            (p0, q0, p1, q1) = (p1, q1, p0 + a * p1, q2);
+            (n, d) = (d, n - a * d);
+        }
+
+        // the best approximation is either the last convergent or the semiconvergent before it
+        let k = (max - q0) / q1;
+        let big = |n: i128, d: i128| BigRational::new(BigInt::from(n), BigInt::from(d));
+        let exact = big(i128::from(numerator), i128::from(denominator));
+        let (semiconvergent, convergent) = (big(p0 + k * p1, q0 + k * q1), big(p1, q1));
+        let closest = if (&convergent - &exact).abs() <= (&semiconvergent - &exact).abs() {
+            convergent
+        } else {
+            semiconvergent
+        };
+        let to_i64 = |n: &BigInt| {
+            n.to_i64()
+                .expect("Something went wrong! (approximation larger than the value)")
+        };
+        Self::from_ratio(to_i64(closest.numer()), to_i64(closest.denom()))
+    }
+
+    /// The value approximated with `limit_denominator` when there's a `max` denominator
+    pub(crate) fn capped(self, max: Option<i64>) -> Value {
+        match max {
+            Some(max) if self.is_finite() => self.limit_denominator(max),
+            _ => self,
+        }
+    }
+
+    pub fn as_fraction(&self) -> Fraction {
+        let (numerator, denominator) = match self {
+            Self::Infinity => (1, 0),
+            Self::NegInfinity => (-1, 0),
+            Self::Undefined => (0, 0),
+            _ => self.simplify().to_ratio(),
+        };
+        Fraction {
+            numerator,
+            denominator,
+        }
+    }
+
+    /// Whether the reduced denominator is a power of two, so the value has a finite binary expansion
+    /// and is exact in a float with enough range and mantissa (`3/4` is, `1/3` and `1/10` aren't)
+    pub fn is_dyadic(&self) -> bool {
+        self.is_finite() && self.as_fraction().denominator.count_ones() == 1
+    }
+
+    /// `(num_a + num_b) / (den_a + den_b)` of the reduced forms, the fraction between `a` and `b`
+    /// in a Farey sequence or the Stern-Brocot tree
+    /// `1/0` works as the upper bound it is in the tree, `mediant(1, Infinity)` is `2`
+    pub fn mediant(a: Value, b: Value) -> Value {
+        if a == Self::Undefined || b == Self::Undefined {
+            return Self::Undefined;
+        }
+        let (a, b) = (a.as_fraction(), b.as_fraction());
+        let numerator = a.numerator.checked_add(b.numerator).expect("Overflow");
+        let denominator = a.denominator.checked_add(b.denominator).expect("Overflow");
+        if denominator == 0 {
+            return Self::Integer(numerator).divide_by_zero();
+        }
+        Self::from_ratio(numerator, denominator)
+    }
+
+    /// The `n`th harmonic number `1 + 1/2 + ... + 1/n`, exactly. The denominators grow quickly, so
+    /// past `n = 46` this overflows like any other sum
+    pub fn harmonic(n: u32) -> Value {
+        (1..=i64::from(n)).map(|k| Self::from_ratio(1, k)).sum()
+    }
+
+    /// The float nearest to the value, the sentinels become the matching infinity or NaN
+    pub fn to_f64(&self) -> f64 {
+        self.to_f64_rounded(RoundingMode::Nearest)
+    }
+
+    /// Whether the value is within `tolerance` of `other`
+    pub fn approx_eq_f64(&self, other: f64, tolerance: f64) -> bool {
+        (self.to_f64() - other).abs() <= tolerance
+    }
+
+    /// The float the value rounds to under `mode`, values that are exactly representable are returned as-is
+    pub fn to_f64_rounded(&self, mode: RoundingMode) -> f64 {
+        match self {
+            Self::Infinity => return f64::INFINITY,
+            Self::NegInfinity => return f64::NEG_INFINITY,
+            Self::Undefined => return f64::NAN,
+            _ => (),
+        }
+        let (numerator, denominator) = self.to_ratio();
+        let exact = BigRational::new(BigInt::from(numerator), BigInt::from(denominator));
+        let as_exact =
+            |f: f64| BigRational::from_float(f).expect("Something went wrong! (non-finite float)");
+
+        // the division is off by at most an ulp or so, walk it to the largest float not above the value
+        let mut below = numerator as f64 / denominator as f64;
+        while as_exact(below) > exact {
+            below = below.next_down();
+        }
+        while as_exact(below.next_up()) <= exact {
+            below = below.next_up();
+        }
+        if as_exact(below) == exact {
+            return below;
+        }
+        let above = below.next_up();
+
+        match mode {
+            RoundingMode::Down => below,
+            RoundingMode::Up => above,
+            RoundingMode::TowardZero if numerator < 0 => above,
+            RoundingMode::TowardZero => below,
+            RoundingMode::Nearest => {
+                match (&exact - as_exact(below)).cmp(&(as_exact(above) - &exact)) {
+                    Ordering::Less => below,
+                    Ordering::Greater => above,
+                    Ordering::Equal if below.to_bits() & 1 == 0 => below,
+                    Ordering::Equal => above,
+                }
+            }
+        }
+    }
+
+    /// The `(whole, numerator, denominator)` of the reduced mixed number, with the sign on the whole
+    /// part (`-7/2` -> `(-3, 1, 2)`), or on the numerator when there's no whole part (`-1/2` -> `(0, -1, 2)`).
+    /// The non-finite values have no whole part and the fraction of `as_fraction`, `(0, -1, 0)`
+    pub fn to_mixed(&self) -> (i64, i64, i64) {
+        let Fraction {
+            numerator,
+            denominator,
+        } = self.as_fraction();
+        if denominator == 0 {
+            return (0, numerator, 0);
+        }
+        let (whole, remainder) = (numerator / denominator, numerator % denominator);
+
+        if whole == 0 {
+            (0, remainder, denominator)
+        } else {
+            (whole, remainder.abs(), denominator)
+        }
+    }
+
+    /// The value as a decimal rounded to `places` digits after the point (half away from zero),
+    /// falling back to `n/d` when the scaled value doesn't fit
+    pub fn to_decimal(&self, places: usize) -> String {
+        if !self.is_finite() {
+            return self.format(DisplayStyle::Decimal);
+        }
+        let Fraction {
+            numerator,
+            denominator,
+        } = self.as_fraction();
+        let scaled = u32::try_from(places)
+            .ok()
+            .and_then(|places| 10i128.checked_pow(places))
+            .and_then(|scale| i128::from(numerator).checked_mul(scale));
+        let scaled = match scaled {
+            Some(scaled) => scaled,
+            None => return self.as_fraction().to_string(),
+        };
+
+        let denominator = i128::from(denominator);
+        let (mut digits, remainder) = (scaled / denominator, scaled % denominator);
+        if 2 * remainder.abs() >= denominator {
+            digits += scaled.signum();
+        }
+
+        let sign = if digits < 0 { "-" } else { "" };
+        let digits = format!("{:0>width$}", digits.unsigned_abs(), width = places + 1);
+        let (whole, fractional) = digits.split_at(digits.len() - places);
+        if places == 0 {
+            format!("{}{}", sign, whole)
+        } else {
+            format!("{}{}.{}", sign, whole, fractional)
+        }
+    }
+
+    /// Write the value in `style` rather than the configured `Config::display`, decimals still take
+    /// `Config::precision` places
+    pub fn format(&self, style: DisplayStyle) -> String {
+        match (self, style) {
+            (Self::Infinity, _) => "inf".to_string(),
+            (Self::NegInfinity, _) => "-inf".to_string(),
+            (Self::Undefined, _) => "undefined".to_string(),
+            (_, DisplayStyle::Fraction) => self.as_fraction().to_string(),
+            (_, DisplayStyle::Decimal) => self.to_decimal(config().precision),
+            (Self::Integer(i), DisplayStyle::Mixed) => i.to_string(),
+            (
+                Self::Rational {
+                    quotient,
+                    remainder,
+                    divisor,
+                },
+                DisplayStyle::Mixed,
+            ) => format!("{} ({} / {})", quotient, remainder, divisor),
+        }
+    }
+
+    /// The reduced improper fraction `7/2`, or the bare integer, whatever the internal form or the
+    /// configured `DisplayStyle`. Setting `Config::display` to `DisplayStyle::Fraction` makes this
+    /// what `Display` writes too
+    pub fn to_improper_string(&self) -> String {
+        self.format(DisplayStyle::Fraction)
+    }
+
+    /// The value with a Unicode vulgar fraction for its fractional part when there is one, `3½`,
+    /// and the improper `n/d` otherwise
+    pub fn to_unicode_fraction(&self) -> String {
+        if !self.is_finite() {
+            return self.to_improper_string();
+        }
+        let Fraction {
+            numerator,
+            denominator,
+        } = self.as_fraction();
+        let remainder = (numerator % denominator).abs();
+        match VULGAR_FRACTIONS
+            .iter()
+            .find(|&&(n, d, _)| n == remainder && d == denominator)
+        {
+            Some(&(_, _, glyph)) => {
+                let sign = if numerator < 0 { "-" } else { "" };
+                match (numerator / denominator).unsigned_abs() {
+                    0 => format!("{}{}", sign, glyph),
+                    whole => format!("{}{}{}", sign, whole, glyph),
+                }
+            }
+            None => self.to_improper_string(),
+        }
+    }
+
+    /// The reduced fraction followed by its decimal form to `Config::precision` places without
+    /// trailing zeros, `1/2 (= 0.5)`. Integers are only written once
+    pub fn to_exact_and_decimal(&self) -> String {
+        if !self.is_finite() || self.as_fraction().denominator == 1 {
+            return self.format(DisplayStyle::Fraction);
+        }
+
+        let decimal = self.to_decimal(config().precision);
+        let decimal = if decimal.contains('.') {
+            decimal.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            &decimal
+        };
+        format!("{} (= {})", self.as_fraction(), decimal)
+    }
+
+    /// The float value in engineering notation, a mantissa in `[1, 1000)` to at most 3 decimal
+    /// places with an SI prefix for its power of a thousand, `1500` -> `1.5k`, `0.001` -> `1m`
+    pub fn to_engineering_string(&self) -> String {
+        const PREFIXES: [&str; 17] = [
+            "y", "z", "a", "f", "p", "n", "µ", "m", "", "k", "M", "G", "T", "P", "E", "Z", "Y",
+        ];
+        let value = self.to_f64();
+        if value == 0.0 || !value.is_finite() {
+            return self.to_string();
+        }
+
+        let place = |magnitude: f64| ((magnitude.log10() / 3.0).floor() as i32).clamp(-8, 8);
+        let mut power = place(value.abs());
+        let mut mantissa = value / 1000f64.powi(power);
+        // rounding to 3 places can carry into the next power, 999.9999 -> 1k
+        if (mantissa.abs() * 1000.0).round() >= 1_000_000.0 && power < 8 {
+            power += 1;
+            mantissa /= 1000.0;
+        }
+
+        let digits = format!("{:.3}", mantissa);
+        let digits = digits.trim_end_matches('0').trim_end_matches('.');
+        format!("{}{}", digits, PREFIXES[(power + 8) as usize])
+    }
+
+    /// Render integers as-is and everything else as `\frac{n}{d}`
+    pub fn to_latex(&self) -> String {
+        match self {
+            Self::Infinity => return "\\infty".to_string(),
+            Self::NegInfinity => return "-\\infty".to_string(),
+            Self::Undefined => return "\\text{undefined}".to_string(),
+            _ => (),
+        }
+        let Fraction {
+            numerator,
+            denominator,
+        } = self.as_fraction();
+        if denominator == 1 {
+            numerator.to_string()
+        } else {
+            let sign = if numerator < 0 { "-" } else { "" };
+            format!("{}\\frac{{{}}}{{{}}}", sign, numerator.abs(), denominator)
+        }
+    }
+
+    pub fn simplify(self) -> Self {
+        #[cfg(test)]
+        println!("Simplifying: {:#?}", self);
+        if !self.is_finite() {
+            return self;
+        }
+        let (numerator, denominator) = self.to_ratio();
+        let result = Self::from_ratio(numerator, denominator);
+        #[cfg(test)]
+        println!("Result: {:#?}", result);
+        result
+    }
+
+    /// Raise the value to an integer power, negative exponents take the exact reciprocal
+    pub fn pow(self, exponent: i64) -> Self {
+        if exponent < 0 && self.sign() == Some(0) {
+            panic!("Divide by zero");
+        }
+        self.checked_powi(exponent).expect("Overflow")
+    }
+
+    /// `self / rhs` rounded toward negative infinity
+    pub fn div_floor(self, rhs: Self) -> Self {
+        if rhs.sign() == Some(0) {
+            panic!("Divide by zero");
+        }
+        self.checked_div_floor(rhs).expect("Overflow")
+    }
+
+    /// The remainder of `self / rhs`, with the sign chosen by `convention`
+    pub fn rem(self, rhs: Self, convention: Modulo) -> Self {
+        if rhs.sign() == Some(0) {
+            panic!("Divide by zero");
+        }
+        self.checked_rem(rhs, convention).expect("Overflow")
+    }
+
+    /// The `(prime, exponent)` pairs of a positive integer value, in ascending order of prime
+    pub fn prime_factors(&self) -> Result<Vec<(i64, u32)>, EvalError> {
+        let mut n = match self.simplify() {
+            Self::Integer(n) if n > 0 => n,
+            Self::Rational { .. } => return Err(EvalError::NotAnInteger),
+            _ => return Err(EvalError::OutOfDomain),
+        };
+
+        let mut factors = Vec::new();
+        let mut prime = 2;
+        while prime <= n / prime {
+            let mut exponent = 0;
+            while n % prime == 0 {
+                n /= prime;
+                exponent += 1;
+            }
+            if exponent > 0 {
+                factors.push((prime, exponent));
+            }
+            prime += if prime == 2 { 1 } else { 2 };
+        }
+        if n > 1 {
+            factors.push((n, 1));
+        }
+
+        Ok(factors)
+    }
+
+    /// `base ^ exp mod modulus` by square-and-multiply, for integer values with a non-negative
+    /// exponent and a positive modulus. The result is in `[0, modulus)`
+    pub fn pow_mod(base: Value, exp: Value, modulus: Value) -> Result<Value, EvalError> {
+        let (base, mut exp, modulus) = (
+            i64::try_from(base)?,
+            i64::try_from(exp)?,
+            i64::try_from(modulus)?,
+        );
+        if exp < 0 || modulus <= 0 {
+            return Err(EvalError::OutOfDomain);
+        }
+
+        let modulus = i128::from(modulus);
+        let (mut result, mut base) = (1 % modulus, i128::from(base).rem_euclid(modulus));
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result * base % modulus;
+            }
+            base = base * base % modulus;
+            exp >>= 1;
+        }
+        Ok(Self::Integer(i64::try_from(result).expect(
+            "Something went wrong! (residue larger than modulus)",
+        )))
+    }
+
+    pub fn is_power_of_two(&self) -> bool {
+        matches!(self.simplify(), Self::Integer(n) if n > 0 && n & (n - 1) == 0)
+    }
+
+    /// The smallest power of two at least as large as a non-negative integer value
+    pub fn next_power_of_two(&self) -> Result<Value, EvalError> {
+        let n = match self.simplify() {
+            Self::Integer(n) if n >= 0 => n as u64,
+            Self::Rational { .. } => return Err(EvalError::NotAnInteger),
+            _ => return Err(EvalError::OutOfDomain),
+        };
+
+        n.checked_next_power_of_two()
+            .and_then(|power| i64::try_from(power).ok())
+            .map(Self::Integer)
+            .ok_or(EvalError::Overflow)
+    }
+
+    pub fn abs(self) -> Self {
+        if self.sign() == Some(-1) {
+            -self
+        } else {
+            self
+        }
+    }
+
+    /// `|self - other|`
+    pub fn abs_diff(self, other: Value) -> Value {
+        (self - other).abs()
+    }
+
+    /// Format the value with an explicit leading sign, zero is formatted as `+0`. `Undefined` has no
+    /// sign and is written as it is
+    pub fn format_signed(&self) -> String {
+        let sign = match self.sign() {
+            Some(-1) => '-',
+            Some(_) => '+',
+            None => return self.to_string(),
+        };
+        format!("{}{}", sign, self.abs())
+    }
+
+    /// The largest integer not above the value, `-7/2` -> `-4`. The non-finite values are their own floor
+    pub fn floor(&self) -> Value {
+        if !self.is_finite() {
+            return *self;
+        }
+        let (numerator, denominator) = self.to_ratio();
+        Self::Integer(numerator.div_euclid(denominator))
+    }
+
+    /// The smallest integer not below the value, `-7/2` -> `-3`. The non-finite values are their own ceiling
+    pub fn ceil(&self) -> Value {
+        if !self.is_finite() {
+            return *self;
+        }
+        let (numerator, denominator) = self.to_ratio();
+        let floor = numerator.div_euclid(denominator);
+        if numerator.rem_euclid(denominator) == 0 {
+            Self::Integer(floor)
+        } else {
+            Self::Integer(floor + 1)
+        }
+    }
+
+    /// Round to the nearest integer, ties go to the even neighbour (`5/2` -> 2, `7/2` -> 4). The
+    /// non-finite values saturate like an `f64` cast, the infinities to `i64::MAX` and `i64::MIN` and
+    /// `Undefined` to 0
+    pub fn round_half_even(&self) -> i64 {
+        match self {
+            Self::Infinity => return i64::MAX,
+            Self::NegInfinity => return i64::MIN,
+            Self::Undefined => return 0,
+            _ => (),
+        }
+        let (numerator, denominator) = self.to_ratio();
+        let floor = numerator.div_euclid(denominator);
+        // against how far the ceiling is rather than twice the remainder, which overflows
+        let remainder = numerator.rem_euclid(denominator);
+        let to_ceiling = denominator - remainder;
+
+        if remainder < to_ceiling || (remainder == to_ceiling && floor % 2 == 0) {
+            floor
+        } else {
+            floor + 1
+        }
+    }
+
+    /// A random value in `[min, max]` with a denominator of at most `RANDOM_MAX_DENOMINATOR`, or
+    /// `min` if there's no such value in the range. The same seed always gives the same value
+    #[cfg(feature = "rand")]
+    pub fn random_in(min: Value, max: Value, rng: &mut impl rand::Rng) -> Value {
+        if !min.is_finite() || !max.is_finite() {
+            panic!("The range must be finite, got [{}, {}]", min, max);
+        }
+        if min > max {
+            panic!("Empty range, {} > {}", min, max);
+        }
+        let ((min_numerator, min_denominator), (max_numerator, max_denominator)) =
+            (min.to_ratio(), max.to_ratio());
+
+        // the multiples of 1/denominator in the range, min * denominator rounded up to max * denominator rounded down
+        let denominator = rng.gen_range(1..=RANDOM_MAX_DENOMINATOR);
+        let scale = |numerator: i64, divisor: i64| {
+            (
+                i128::from(numerator) * i128::from(denominator),
+                i128::from(divisor),
+            )
+        };
+        let (low, divisor) = scale(min_numerator, min_denominator);
+        let low = low.div_euclid(divisor) + i128::from(low.rem_euclid(divisor) != 0);
+        let (high, divisor) = scale(max_numerator, max_denominator);
+        let high = high.div_euclid(divisor);
+        if low > high {
+            return min;
+        }
+
+        let numerator = i64::try_from(rng.gen_range(low..=high)).expect("Overflow");
+        Self::from_ratio(numerator, denominator)
+    }
+}
+
+/// The largest denominator `Value::random_in` picks
+#[cfg(feature = "rand")]
+pub const RANDOM_MAX_DENOMINATOR: i64 = 1000;
+
+/// The Farey sequence of order `n`, every reduced fraction in `[0, 1]` with a denominator of at
+/// most `n`, in ascending order
+pub fn farey(n: i64) -> impl Iterator<Item = Value> {
+    // each term follows from the two before it, starting from 0/1 and 1/n
+    let mut terms = (0, 1, 1, n);
+    std::iter::from_fn(move || {
+        let (a, b, c, d) = terms;
+        if n < 1 || a > b {
+            return None;
+        }
+        let k = (n + b) / d;
+        terms = (c, d, k * c - a, k * d - b);
+        Some(Value::from_ratio(a, b))
+    })
+}
+
+/// Sort `values` into ascending order, with any `Undefined` at the end
+pub fn sort_values(values: &mut [Value]) {
+    values.sort_by(|lhs, rhs| {
+        lhs.partial_cmp(rhs)
+            .unwrap_or_else(|| (*lhs == Value::Undefined).cmp(&(*rhs == Value::Undefined)))
+    });
+}
+
+/// The exact middle of `values`, the mean of the two middle values when there's an even number of
+/// them, `None` if there are no values
+pub fn median(values: &[Value]) -> Option<Value> {
+    let mut sorted = values.to_vec();
+    sort_values(&mut sorted);
+
+    let middle = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        n if n % 2 == 1 => Some(sorted[middle]),
+        _ => Some((sorted[middle - 1] + sorted[middle]) / Value::Integer(2)),
+    }
+}
+
+/// The nearest float to each of `values`, in order, for consumers like plotting that want raw floats
+pub fn values_to_f64(values: &[Value]) -> Vec<f64> {
+    values.iter().map(Value::to_f64).collect()
+}
+
+/// The least common multiple of the reduced denominators of `values`, so they can all be written
+/// over one denominator. `1` for integers or no values at all, the non-finite values are skipped
+pub fn common_denominator(values: &[Value]) -> i64 {
+    values
+        .iter()
+        .filter(|value| value.is_finite())
+        .fold(1, |lcm, value| {
+            let denominator = value.as_fraction().denominator;
+            (lcm / gcd(lcm, denominator))
+                .checked_mul(denominator)
+                .expect("Overflow")
+        })
+}
+
+/// `gcd` without the overflow on `i64::MIN`, `None` if the result doesn't fit
+fn checked_gcd(a: i64, b: i64) -> Option<i64> {
+    i64::try_from(gcd(a.unsigned_abs(), b.unsigned_abs())).ok()
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if !self.is_finite() || !other.is_finite() {
+            return match (self, other) {
+                (Self::Undefined, _) | (_, Self::Undefined) => None,
+                (Self::Infinity, Self::Infinity) | (Self::NegInfinity, Self::NegInfinity) => {
+                    Some(Ordering::Equal)
+                }
+                (Self::Infinity, _) | (_, Self::NegInfinity) => Some(Ordering::Greater),
+                _ => Some(Ordering::Less),
+            };
+        }
+
+        let (lhs_numerator, lhs_denominator) = self.checked_to_ratio()?;
+        let (rhs_numerator, rhs_denominator) = other.checked_to_ratio()?;
+
+        // compare a/b against c/d as a*d against c*b, with the denominators made positive
+        let lhs = i128::from(lhs_numerator) * i128::from(rhs_denominator);
+        let rhs = i128::from(rhs_numerator) * i128::from(lhs_denominator);
+        if (lhs_denominator < 0) == (rhs_denominator < 0) {
+            Some(lhs.cmp(&rhs))
+        } else {
+            Some(rhs.cmp(&lhs))
+        }
+    }
+}
+impl Value {
+    /// The finite value as a reduced numerator and positive denominator, wide enough that any
+    /// fields fit, the key equality and hashing go by
+    fn ratio_key(&self) -> Option<(i128, i128)> {
+        let (numerator, denominator) = match *self {
+            Self::Integer(i) => return Some((i.into(), 1)),
+            Self::Rational {
+                quotient,
+                remainder,
+                divisor,
+            } => (
+                i128::from(quotient) * i128::from(divisor) + i128::from(remainder),
+                i128::from(divisor),
+            ),
+            _ => return None,
+        };
+        let divisor = gcd(numerator, denominator) * denominator.signum();
+        Some((numerator / divisor, denominator / divisor))
+    }
+}
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.ratio_key(), other.ratio_key()) {
+            (Some(lhs), Some(rhs)) => lhs == rhs,
+            (None, None) => std::mem::discriminant(self) == std::mem::discriminant(other),
+            _ => false,
+        }
+    }
+}
+// `Undefined` is equal to itself, unlike a float NaN
+impl Eq for Value {}
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self.ratio_key() {
+            Some(key) => key.hash(state),
+            None => std::mem::discriminant(self).hash(state),
+        }
+    }
+}
+impl PartialEq<i64> for Value {
+    fn eq(&self, other: &i64) -> bool {
+        *self == Self::Integer(*other)
+    }
+}
+impl PartialEq<Rational64> for Value {
+    fn eq(&self, other: &Rational64) -> bool {
+        self.checked_to_ratio()
+            .is_some_and(|(numerator, denominator)| {
+                Rational64::new(numerator, denominator) == *other
+            })
+    }
+}
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Self::Integer(i)
+    }
+}
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
+        Self::checked_from_f64(f).unwrap_or_else(|| panic!("Cannot represent {} as a Value", f))
+    }
+}
+impl From<Value> for f64 {
+    fn from(v: Value) -> Self {
+        v.to_f64()
+    }
+}
+impl From<Rational64> for Value {
+    fn from(r: Rational64) -> Self {
+        Self::from_ratio(*r.numer(), *r.denom())
+    }
+}
+impl TryFrom<Value> for Rational64 {
+    type Error = EvalError;
+
+    /// The non-finite values are out of the domain of a ratio
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        if !v.is_finite() {
+            return Err(EvalError::OutOfDomain);
+        }
+        let (numerator, denominator) = v.checked_to_ratio().ok_or(EvalError::Overflow)?;
+        Ok(Rational64::new(numerator, denominator))
+    }
+}
+impl TryFrom<&str> for Value {
+    type Error = EvalError;
+
+    /// A literal `numerator/denominator` is built directly from the two integers, anything else is
+    /// evaluated as an expression
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let s = s.trim();
+        let literal = s.split_once('/').and_then(|(numerator, denominator)| {
+            Some((numerator.parse().ok()?, denominator.parse().ok()?))
+        });
+        match literal {
+            Some((numerator, denominator)) => Value::new_rational(numerator, denominator),
+            None => crate::tree::Tree::try_new(s)?.evaluate_with(&std::collections::HashMap::new()),
+        }
+    }
+}
+impl TryFrom<Value> for i64 {
+    type Error = EvalError;
+
+    /// Succeeds for any integer-valued form, including unreduced ones like `1 (2 / 2)`
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        let reduced = match v {
+            Value::Rational { .. } => v
+                .checked_to_ratio()
+                .and_then(|(numerator, denominator)| {
+                    Value::checked_from_ratio(numerator, denominator)
+                })
+                .ok_or(EvalError::Overflow)?,
+            _ => v,
+        };
+        match reduced {
+            Value::Integer(i) => Ok(i),
+            _ => Err(EvalError::NotAnInteger),
+        }
+    }
+}
+#[cfg(feature = "decimal")]
+impl From<rust_decimal::Decimal> for Value {
+    fn from(d: rust_decimal::Decimal) -> Self {
+        Self::checked_from_decimal(d).unwrap_or_else(|| panic!("Cannot represent {} as a Value", d))
+    }
+}
+#[cfg(feature = "decimal")]
+impl TryFrom<Value> for rust_decimal::Decimal {
+    type Error = EvalError;
+
+    /// Exact when the denominator only has factors of 2 and 5, otherwise rounded to `Decimal`'s precision.
+    /// `Decimal` has no infinities, the non-finite values are out of its domain
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        if !v.is_finite() {
+            return Err(EvalError::OutOfDomain);
+        }
+        let (numerator, denominator) = v.checked_to_ratio().ok_or(EvalError::Overflow)?;
+        Ok(rust_decimal::Decimal::from(numerator) / rust_decimal::Decimal::from(denominator))
+    }
+}
+/// The result of an arithmetic operator that failed with `error`, saturating toward the sign given
+/// by `negative` if the configured `OverflowMode` allows it
+fn overflowed(error: EvalError, negative: bool) -> Value {
+    match (error, config().overflow) {
+        (EvalError::Overflow, OverflowMode::Saturate) if negative => Value::Integer(i64::MIN),
+        (EvalError::Overflow, OverflowMode::Saturate) => Value::Integer(i64::MAX),
+        (e, _) => panic!("{}", e),
+    }
+}
+impl Add for Value {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        self.try_add(rhs)
+            .unwrap_or_else(|e| overflowed(e, self < Value::Integer(0)))
+    }
+}
+impl Sub for Value {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self.try_sub(rhs)
+            .unwrap_or_else(|e| overflowed(e, self < Value::Integer(0)))
+    }
+}
+impl Mul for Value {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        self.try_mul(rhs).unwrap_or_else(|e| {
+            overflowed(e, (self < Value::Integer(0)) != (rhs < Value::Integer(0)))
+        })
+    }
+}
+/// The quotient is always reduced, so a whole result is an `Integer` (`6/3` -> `2`) and anything
+/// else is a `Rational` in lowest terms (`6/4` -> `3/2`)
+impl Div for Value {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        self.try_div(rhs).unwrap_or_else(|e| {
+            overflowed(e, (self < Value::Integer(0)) != (rhs < Value::Integer(0)))
+        })
+    }
+}
+impl Neg for Value {
+    type Output = Self;
+
+    /// `-i64::MIN` doesn't fit, so negating it overflows like the other operators do
+    fn neg(self) -> Self::Output {
+        // negating the fields keeps the form the value is in, `checked_neg` goes through the ratio
+        let negated =
+            match self {
+                Self::Integer(i) => i.checked_neg().map(Self::Integer),
+                Self::Rational {
+                    quotient,
+                    remainder,
+                    divisor,
+                } => quotient.checked_neg().zip(remainder.checked_neg()).map(
+                    |(quotient, remainder)| Self::Rational {
+                        quotient,
+                        remainder,
+                        divisor,
+                    },
+                ),
+                _ => None,
+            };
+        negated
+            .or_else(|| self.checked_neg())
+            .unwrap_or_else(|| overflowed(EvalError::Overflow, self > Value::Integer(0)))
+    }
+}
+impl num::Zero for Value {
+    fn zero() -> Self {
+        Value::zero()
+    }
+
+    fn is_zero(&self) -> bool {
+        self.sign() == Some(0)
+    }
+}
+impl num::One for Value {
+    fn one() -> Self {
+        Value::one()
+    }
+}
+impl Sum for Value {
+    fn sum<I: Iterator<Item = Value>>(iter: I) -> Self {
+        iter.fold(Value::zero(), |sum, v| sum + v)
+    }
+}
+impl Product for Value {
+    fn product<I: Iterator<Item = Value>>(iter: I) -> Self {
+        iter.fold(Value::one(), |product, v| product * v)
+    }
+}
+impl<'a> Add<&'a Value> for &'a Value {
+    type Output = Value;
+
+    fn add(self, rhs: &'a Value) -> Self::Output {
+        *self + *rhs
+    }
+}
+impl<'a> Sub<&'a Value> for &'a Value {
+    type Output = Value;
+
+    fn sub(self, rhs: &'a Value) -> Self::Output {
+        *self - *rhs
+    }
+}
+impl<'a> Mul<&'a Value> for &'a Value {
+    type Output = Value;
+
+    fn mul(self, rhs: &'a Value) -> Self::Output {
+        *self * *rhs
+    }
+}
+impl<'a> Div<&'a Value> for &'a Value {
+    type Output = Value;
+
+    fn div(self, rhs: &'a Value) -> Self::Output {
+        *self / *rhs
+    }
+}
+impl Neg for &Value {
+    type Output = Value;
+
+    fn neg(self) -> Self::Output {
+        -*self
+    }
+}
+impl Add<i64> for Value {
+    type Output = Value;
+
+    fn add(self, rhs: i64) -> Self::Output {
+        self + Value::from(rhs)
+    }
+}
+impl Add<Value> for i64 {
+    type Output = Value;
+
+    fn add(self, rhs: Value) -> Self::Output {
+        Value::from(self) + rhs
+    }
+}
+impl Sub<i64> for Value {
+    type Output = Value;
+
+    fn sub(self, rhs: i64) -> Self::Output {
+        self - Value::from(rhs)
+    }
+}
+impl Sub<Value> for i64 {
+    type Output = Value;
+
+    fn sub(self, rhs: Value) -> Self::Output {
+        Value::from(self) - rhs
+    }
+}
+impl Mul<i64> for Value {
+    type Output = Value;
+
+    fn mul(self, rhs: i64) -> Self::Output {
+        self * Value::from(rhs)
+    }
+}
+impl Mul<Value> for i64 {
+    type Output = Value;
+
+    fn mul(self, rhs: Value) -> Self::Output {
+        Value::from(self) * rhs
+    }
+}
+impl Div<i64> for Value {
+    type Output = Value;
+
+    fn div(self, rhs: i64) -> Self::Output {
+        self / Value::from(rhs)
+    }
+}
+impl Div<Value> for i64 {
+    type Output = Value;
+
+    fn div(self, rhs: Value) -> Self::Output {
+        Value::from(self) / rhs
+    }
+}
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.format(config().display))
+    }
+}
+
+#[cfg(test)]
+fn fraction(numerator: i64, denominator: i64) -> Value {
+    Value::new_rational(numerator, denominator).unwrap()
+}
+
+#[test]
+fn test_value_arithmetic() {
+    let half = Value::from(1) / Value::from(2);
+    let third = Value::from(1) / Value::from(3);
+
+    assert_eq!(Value::from(0.5), half);
+    assert_eq!(Value::from(-0.5), -half);
+    assert_eq!(Value::from(2.0), Value::from(2));
+
+    assert_eq!(half + third, Value::from(5) / Value::from(6));
+    assert_eq!(half - third, Value::from(1) / Value::from(6));
+    assert_eq!(third - half, Value::from(-1) / Value::from(6));
+    assert_eq!(Value::from(1) - half, half);
+    assert_eq!(half - Value::from(1), -half);
+    assert_eq!(Value::from(3) * half, Value::from(1.5));
+    assert_eq!(Value::from(2) / half, Value::from(4));
+    assert_eq!(half / Value::from(2), Value::from(0.25));
+    assert_eq!(-(-half), half);
+    assert_eq!(half + -half, Value::from(0));
+}
+
+#[test]
+fn test_value_arithmetic_with_i64() {
+    let half = Value::from(0.5);
+
+    assert_eq!(half * 2, Value::from(1));
+    assert_eq!(2 * half, Value::from(1));
+    assert_eq!(half + 1, Value::from(1.5));
+    assert_eq!(1 + half, Value::from(1.5));
+    assert_eq!(half - 1, Value::from(-0.5));
+    assert_eq!(1 - half, half);
+    assert_eq!(half / 2, Value::from(0.25));
+    assert_eq!(2 / half, Value::from(4));
+}
+
+#[test]
+fn test_value_try_arithmetic() {
+    let half = Value::from(1) / Value::from(2);
+    let big = Value::from(i64::MAX);
+
+    assert_eq!(half.try_add(half), Ok(Value::from(1)));
+    assert_eq!(half.try_sub(Value::from(1)), Ok(-half));
+    assert_eq!(half.try_mul(Value::from(4)), Ok(Value::from(2)));
+    assert_eq!(half.try_div(half), Ok(Value::from(1)));
+
+    assert_eq!(big.try_mul(Value::from(2)), Err(EvalError::Overflow));
+    assert_eq!(big.try_add(Value::from(1)), Err(EvalError::Overflow));
+    assert_eq!(
+        (Value::from(1) / big).try_mul(half),
+        Err(EvalError::Overflow)
+    );
+    assert_eq!(half.try_div(Value::from(0)), Err(EvalError::DivideByZero));
+    assert_eq!(
+        Value::from(0).try_div(Value::from(0)),
+        Err(EvalError::DivideByZero)
+    );
+}
+
+#[test]
+fn test_value_recip_checked() {
+    assert_eq!(Value::Integer(0).recip_checked(), None);
+    assert_eq!(
+        Value::Integer(4).recip_checked(),
+        Some(Value::from(1) / Value::from(4))
+    );
+    assert_eq!(Value::Integer(-1).recip_checked(), Some(Value::from(-1)));
+    assert_eq!(
+        (Value::from(-2) / Value::from(3)).recip_checked(),
+        Some(Value::from(-1.5))
+    );
+    assert_eq!(Value::from(0.25).recip_checked(), Some(Value::from(4)));
+    assert_eq!(Value::from(i64::MIN).recip_checked(), None);
+}
+
+#[test]
+fn test_value_divide_exact() {
+    // division always reduces, whole quotients come back as integers
+    assert_eq!(Value::from(6) / Value::from(3), Value::Integer(2));
+    assert_eq!(
+        Value::from(6) / Value::from(4),
+        Value::Rational {
+            quotient: 1,
+            remainder: 1,
+            divisor: 2
+        }
+    );
+
+    assert_eq!(
+        Value::from(6).divide_exact(Value::from(3)),
+        Ok(Value::from(2))
+    );
+    assert_eq!(
+        Value::from(1.5).divide_exact(Value::from(0.5)),
+        Ok(Value::from(3))
+    );
+    assert_eq!(
+        Value::from(7).divide_exact(Value::from(3)),
+        Err(EvalError::NotAnInteger)
+    );
+    assert_eq!(
+        Value::from(7).divide_exact(Value::from(0)),
+        Err(EvalError::DivideByZero)
+    );
+}
+
+#[test]
+fn test_value_arithmetic_by_reference() {
+    let half = &(Value::from(1) / Value::from(2));
+    let third = &(Value::from(1) / Value::from(3));
+
+    assert_eq!(half + third, *half + *third);
+    assert_eq!(half - third, *half - *third);
+    assert_eq!(third * half, *third * *half);
+    assert_eq!(third / half, *third / *half);
+    assert_eq!(-half, -*half);
+
+    let six = &Value::from(6);
+    assert_eq!(&(half + third) * six, Value::from(5));
+}
+
+#[test]
+fn test_value_pow() {
+    let half = Value::from(1) / Value::from(2);
+
+    assert_eq!(Value::from(3).pow(0), Value::from(1));
+    assert_eq!(Value::from(-3).pow(3), Value::from(-27));
+    assert_eq!(half.pow(2), Value::from(0.25));
+    assert_eq!(half.pow(-2), Value::from(4));
+    assert_eq!(Value::from(-2).pow(-1), -half);
+}
+
+#[test]
+fn test_value_floor_ceil() {
+    assert_eq!(fraction(7, 2).floor(), Value::Integer(3));
+    assert_eq!(fraction(7, 2).ceil(), Value::Integer(4));
+    assert_eq!(fraction(-7, 2).floor(), Value::Integer(-4));
+    assert_eq!(fraction(-7, 2).ceil(), Value::Integer(-3));
+    assert_eq!(fraction(1, 3).floor(), Value::Integer(0));
+    assert_eq!(fraction(1, 3).ceil(), Value::Integer(1));
+    assert_eq!(fraction(-1, 3).floor(), Value::Integer(-1));
+    assert_eq!(fraction(-1, 3).ceil(), Value::Integer(0));
+    assert_eq!(Value::from(-5).floor(), Value::Integer(-5));
+    assert_eq!(Value::from(-5).ceil(), Value::Integer(-5));
+}
+
+#[test]
+fn test_value_round_half_even() {
+    assert_eq!((Value::from(5) / Value::from(2)).round_half_even(), 2);
+    assert_eq!((Value::from(7) / Value::from(2)).round_half_even(), 4);
+    assert_eq!((Value::from(-5) / Value::from(2)).round_half_even(), -2);
+    assert_eq!((Value::from(-7) / Value::from(2)).round_half_even(), -4);
+
+    assert_eq!((Value::from(7) / Value::from(3)).round_half_even(), 2);
+    assert_eq!((Value::from(-8) / Value::from(3)).round_half_even(), -3);
+    assert_eq!(Value::from(0.6).round_half_even(), 1);
+    assert_eq!(Value::from(9).round_half_even(), 9);
+    assert_eq!(
+        Value::new_rational(i64::MAX - 1, i64::MAX)
+            .unwrap()
+            .round_half_even(),
+        1
+    );
+    assert_eq!(fraction(-1, i64::MAX).round_half_even(), 0);
+}
+
+#[test]
+fn test_value_abs_diff() {
+    let half = Value::from(1) / Value::from(2);
+    let third = Value::from(1) / Value::from(3);
+
+    assert_eq!(half.abs_diff(third), Value::from(1) / Value::from(6));
+    assert_eq!(third.abs_diff(half), half.abs_diff(third));
+    assert_eq!((-half).abs_diff(third), Value::from(5) / Value::from(6));
+    assert_eq!(third.abs_diff(-half), (-half).abs_diff(third));
+    assert_eq!(half.abs_diff(half), Value::from(0));
+}
+
+#[test]
+fn test_value_format_signed() {
+    assert_eq!(Value::from(3).format_signed(), "+3");
+    assert_eq!(Value::from(-5).format_signed(), "-5");
+    assert_eq!(Value::from(0).format_signed(), "+0");
+    assert_eq!(Value::from(0.5).format_signed(), "+0 (1 / 2)");
+    assert_eq!(Value::from(-3.5).format_signed(), "-3 (1 / 2)");
+}
+
+#[test]
+fn test_value_rem() {
+    assert_eq!(
+        Value::from(7).rem(3.into(), Modulo::Floored),
+        Value::from(1)
+    );
+    assert_eq!(
+        Value::from(-7).rem(3.into(), Modulo::Floored),
+        Value::from(2)
+    );
+    assert_eq!(
+        Value::from(7).rem((-3).into(), Modulo::Floored),
+        Value::from(-2)
+    );
+    assert_eq!(
+        Value::from(-7).rem(3.into(), Modulo::Truncated),
+        Value::from(-1)
+    );
+    assert_eq!(
+        Value::from(7).rem((-3).into(), Modulo::Truncated),
+        Value::from(1)
+    );
+    assert_eq!(
+        Value::from(3.5).rem(1.into(), Modulo::Floored),
+        Value::from(0.5)
+    );
+    assert_eq!(
+        Value::from(-3.5).rem(1.into(), Modulo::Floored),
+        Value::from(0.5)
+    );
+    assert_eq!(
+        Value::from(-3.5).rem(1.into(), Modulo::Truncated),
+        Value::from(-0.5)
+    );
+}
+
+#[test]
+fn test_value_prime_factors() {
+    assert_eq!(
+        Value::from(360).prime_factors(),
+        Ok(vec![(2, 3), (3, 2), (5, 1)])
+    );
+    assert_eq!(Value::from(97).prime_factors(), Ok(vec![(97, 1)]));
+    assert_eq!(Value::from(1).prime_factors(), Ok(vec![]));
+    assert_eq!(Value::from(4.0).prime_factors(), Ok(vec![(2, 2)]));
+
+    assert_eq!(
+        Value::from(0.5).prime_factors(),
+        Err(EvalError::NotAnInteger)
+    );
+    assert_eq!(Value::from(0).prime_factors(), Err(EvalError::OutOfDomain));
+    assert_eq!(
+        Value::from(-12).prime_factors(),
+        Err(EvalError::OutOfDomain)
+    );
+}
+
+#[test]
+fn test_value_power_of_two() {
+    assert!(Value::from(8).is_power_of_two());
+    assert!(Value::from(1).is_power_of_two());
+    assert!(!Value::from(6).is_power_of_two());
+    assert!(!Value::from(0).is_power_of_two());
+    assert!(!Value::from(-8).is_power_of_two());
+    assert!(!Value::from(0.5).is_power_of_two());
+
+    assert_eq!(Value::from(5).next_power_of_two(), Ok(Value::from(8)));
+    assert_eq!(Value::from(8).next_power_of_two(), Ok(Value::from(8)));
+    assert_eq!(Value::from(0).next_power_of_two(), Ok(Value::from(1)));
+    assert_eq!(
+        Value::from(1.5).next_power_of_two(),
+        Err(EvalError::NotAnInteger)
+    );
+    assert_eq!(
+        Value::from(-5).next_power_of_two(),
+        Err(EvalError::OutOfDomain)
+    );
+    assert_eq!(
+        Value::from(i64::MAX).next_power_of_two(),
+        Err(EvalError::Overflow)
+    );
+}
+
+#[test]
+fn test_farey() {
+    assert_eq!(
+        farey(3).collect::<Vec<_>>(),
+        vec![
+            Value::from(0),
+            fraction(1, 3),
+            fraction(1, 2),
+            fraction(2, 3),
+            Value::from(1)
+        ]
+    );
+    assert_eq!(
+        farey(1).collect::<Vec<_>>(),
+        vec![Value::from(0), Value::from(1)]
+    );
+    assert_eq!(farey(0).count(), 0);
+    // 1 + the sum of Euler's totient up to 10
+    assert_eq!(farey(10).count(), 33);
+    assert!(farey(10).zip(farey(10).skip(1)).all(|(lhs, rhs)| lhs < rhs));
+}
+
+#[test]
+fn test_value_as_fraction() {
+    let fraction = Value::from(6).as_fraction();
+    assert_eq!((fraction.numerator, fraction.denominator), (6, 1));
+    assert_eq!(fraction.to_string(), "6");
+
+    let fraction = Value::from(0.75).as_fraction();
+    assert_eq!((fraction.numerator, fraction.denominator), (3, 4));
+    assert_eq!(fraction.to_string(), "3/4");
+
+    let fraction = (Value::from(-7) / Value::from(2)).as_fraction();
+    assert_eq!((fraction.numerator, fraction.denominator), (-7, 2));
+    assert_eq!(fraction.to_string(), "-7/2");
+
+    // unreduced internal forms still give the reduced fraction
+    let fraction = Value::Rational {
+        quotient: 1,
+        remainder: 4,
+        divisor: -8,
+    }
+    .as_fraction();
+    assert_eq!(fraction.to_string(), "1/2");
+}
+
+#[test]
+fn test_value_to_mixed() {
+    assert_eq!(fraction(7, 2).to_mixed(), (3, 1, 2));
+    assert_eq!(fraction(-7, 2).to_mixed(), (-3, 1, 2));
+    assert_eq!(fraction(14, -4).to_mixed(), (-3, 1, 2));
+    assert_eq!(fraction(-1, 2).to_mixed(), (0, -1, 2));
+    assert_eq!(fraction(2, 3).to_mixed(), (0, 2, 3));
+    assert_eq!(Value::from(5).to_mixed(), (5, 0, 1));
+    assert_eq!(Value::from(-5).to_mixed(), (-5, 0, 1));
+    assert_eq!(Value::from(0).to_mixed(), (0, 0, 1));
+}
+
+#[test]
+fn test_value_mul_integer() {
+    let rationals = vec![
+        Value::from(0.5),
+        Value::from(-0.5),
+        Value::from(3.25),
+        Value::from(-7) / Value::from(3),
+        Value::Rational {
+            quotient: 2,
+            remainder: 6,
+            divisor: 4,
+        },
+    ];
+
+    for lhs in rationals {
+        for rhs in [-4, -1, 0, 1, 2, 3, 6] {
+            // the same product through the rational * rational path
+            let expected = lhs
+                * Value::Rational {
+                    quotient: rhs,
+                    remainder: 0,
+                    divisor: 1,
+                };
+            assert_eq!(lhs * Value::from(rhs), expected);
+            assert_eq!(Value::from(rhs) * lhs, expected);
+        }
+    }
+}
+
+#[cfg(feature = "decimal")]
+#[test]
+fn test_value_decimal() {
+    use rust_decimal::Decimal;
+    use std::str::FromStr;
+
+    let tenth = Value::from(Decimal::from_str("0.1").unwrap());
+    let fifth = Value::from(Decimal::from_str("0.2").unwrap());
+    assert_eq!(tenth, Value::from(1) / Value::from(10));
+    assert_eq!(
+        tenth + fifth,
+        Value::from(Decimal::from_str("0.3").unwrap())
+    );
+    assert_eq!(
+        Decimal::try_from(tenth + fifth),
+        Ok(Decimal::from_str("0.3").unwrap())
+    );
+    assert_eq!(
+        Decimal::try_from(Value::NegInfinity),
+        Err(EvalError::OutOfDomain)
+    );
+    assert_eq!(
+        Value::from(Decimal::from_str("-12.50").unwrap()),
+        Value::from(-12.5)
+    );
+}
+
+#[test]
+fn test_value_mediant() {
+    let half = Value::from(1) / Value::from(2);
+    let third = Value::from(1) / Value::from(3);
+
+    assert_eq!(Value::mediant(half, third), Value::from(2) / Value::from(5));
+    assert_eq!(Value::mediant(third, half), Value::from(2) / Value::from(5));
+    assert_eq!(
+        Value::mediant(Value::from(0), Value::from(1)),
+        Value::from(0.5)
+    );
+    // the reduced forms are used, 2/4 is 1/2
+    assert_eq!(
+        Value::mediant(Value::from(0.5), Value::from(1)),
+        Value::from(2) / Value::from(3)
+    );
+    assert_eq!(Value::mediant(-half, half), Value::from(0));
+}
+
+#[test]
+fn test_value_checked_pow() {
+    let two = Value::from(2);
+
+    assert_eq!(two.checked_pow(62), Some(Value::from(1i64 << 62)));
+    assert_eq!(two.checked_pow(63), None);
+    assert_eq!(two.checked_pow(64), None);
+    assert_eq!(two.checked_pow(0), Some(Value::from(1)));
+    assert_eq!(Value::from(-3).checked_pow(3), Some(Value::from(-27)));
+    assert_eq!(
+        Value::from(0.5).checked_pow(10),
+        Some(Value::from(1) / Value::from(1024))
+    );
+    assert_eq!(Value::from(1).checked_pow(u32::MAX), Some(Value::from(1)));
+    assert_eq!(Value::from(0.5).checked_pow(63), None);
+}
+
+#[test]
+fn test_value_approx_eq_f64() {
+    let third = Value::from(1) / Value::from(3);
+
+    assert!(third.approx_eq_f64(0.3333, 1e-3));
+    assert!(!third.approx_eq_f64(0.3333, 1e-5));
+    assert!((-third).approx_eq_f64(-0.33, 0.01));
+    assert!(!(-third).approx_eq_f64(0.33, 0.01));
+    assert!(Value::from(2).approx_eq_f64(2.0, 0.0));
+    assert!(!third.approx_eq_f64(f64::NAN, 1.0));
+}
+
+#[test]
+fn test_value_to_f64_rounded() {
+    let third = Value::from(1) / Value::from(3);
+    let down = third.to_f64_rounded(RoundingMode::Down);
+    let up = third.to_f64_rounded(RoundingMode::Up);
+
+    assert_eq!(third.to_f64(), 1.0 / 3.0);
+    assert_eq!(up, down.next_up());
+    assert!(down <= 1.0 / 3.0 && 1.0 / 3.0 <= up);
+    assert_eq!(third.to_f64_rounded(RoundingMode::TowardZero), down);
+    assert_eq!((-third).to_f64_rounded(RoundingMode::TowardZero), -down);
+    assert_eq!((-third).to_f64_rounded(RoundingMode::Down), -up);
+
+    // 2^53 + 1 sits exactly between 2^53 and 2^53 + 2
+    let tie = Value::from((1i64 << 53) + 1);
+    let (even, odd) = (9007199254740992.0, 9007199254740994.0);
+    assert_eq!(tie.to_f64_rounded(RoundingMode::Nearest), even);
+    assert_eq!(tie.to_f64_rounded(RoundingMode::TowardZero), even);
+    assert_eq!(tie.to_f64_rounded(RoundingMode::Down), even);
+    assert_eq!(tie.to_f64_rounded(RoundingMode::Up), odd);
+    assert_eq!(
+        Value::from((1i64 << 53) + 3).to_f64_rounded(RoundingMode::Nearest),
+        9007199254740996.0
+    );
+
+    // exactly representable values aren't rounded
+    for &mode in &[RoundingMode::Nearest, RoundingMode::Up, RoundingMode::Down] {
+        assert_eq!(Value::from(-2.375).to_f64_rounded(mode), -2.375);
+        assert_eq!(Value::from(0).to_f64_rounded(mode), 0.0);
+    }
+}
+
+#[test]
+fn test_value_to_decimal() {
+    assert_eq!(fraction(1, 3).to_decimal(4), "0.3333");
+    assert_eq!(fraction(2, 3).to_decimal(4), "0.6667");
+    assert_eq!(fraction(-2, 3).to_decimal(2), "-0.67");
+    assert_eq!(fraction(-1, 8).to_decimal(2), "-0.13");
+    assert_eq!(fraction(7, 2).to_decimal(0), "4");
+    assert_eq!(fraction(-1, 3).to_decimal(0), "0");
+    assert_eq!(Value::from(12).to_decimal(3), "12.000");
+    assert_eq!(fraction(1, 3).to_decimal(40), "1/3");
+}
+
+#[test]
+fn test_value_to_latex() {
+    assert_eq!(Value::from(0.5).to_latex(), "\\frac{1}{2}");
+    assert_eq!(Value::from(-3.5).to_latex(), "-\\frac{7}{2}");
+    assert_eq!(Value::from(-4).to_latex(), "-4");
+}
+
+#[test]
+fn test_value_checked() {
+    let half = Value::from(0.5);
+    let max = Value::from(i64::MAX);
+
+    assert_eq!(half.checked_add(half), Some(Value::from(1)));
+    assert_eq!(half.checked_sub(Value::from(2)), Some(Value::from(-1.5)));
+    assert_eq!(half.checked_mul(Value::from(6)), Some(Value::from(3)));
+    assert_eq!(Value::from(3).checked_div(half), Some(Value::from(6)));
+    assert_eq!(half.checked_neg(), Some(Value::from(-0.5)));
+
+    assert_eq!(max.checked_add(Value::from(1)), None);
+    assert_eq!(Value::from(i64::MIN).checked_sub(Value::from(1)), None);
+    assert_eq!(max.checked_mul(Value::from(2)), None);
+    assert_eq!(half.checked_div(Value::from(0)), None);
+    assert_eq!(Value::from(i64::MIN).checked_neg(), None);
+    assert_eq!(Value::from(2).checked_powi(64), None);
+    assert_eq!(Value::from(0).checked_powi(-1), None);
+    assert_eq!(Value::from(2).checked_powi(1 << 32), None);
+    assert_eq!(Value::from(1).checked_powi(i64::MAX), Some(Value::from(1)));
+    assert_eq!(Value::from(1).checked_powi(i64::MIN), Some(Value::from(1)));
+    assert_eq!(Value::from(0).checked_powi(i64::MAX), Some(Value::from(0)));
+    assert_eq!(Value::from(0).checked_powi(i64::MIN), None);
+    assert_eq!(
+        Value::from(-1).checked_powi(99_999_999_999),
+        Some(Value::from(-1))
+    );
+    assert_eq!(
+        Value::from(-1).checked_powi(-100_000_000_000),
+        Some(Value::from(1))
+    );
+    assert_eq!(
+        Value::from(i64::MIN).checked_div_floor(Value::from(-1)),
+        None
+    );
+
+    // reducing before multiplying avoids overflowing when the result fits
+    let big = Value::from(i64::MAX) / Value::from(2);
+    assert_eq!(big.checked_mul(Value::from(2)), Some(max));
+}
+
+#[test]
+fn test_value_ordering() {
+    let third = Value::from(1) / Value::from(3);
+    let half = Value::from(0.5);
+
+    assert!(third < half);
+    assert!(-half < -third);
+    assert!(Value::from(-1) < -half);
+    assert!(Value::from(2) > Value::from(1.5));
+    assert!(Value::from(i64::MAX) > Value::from(i64::MAX - 1) / Value::from(2));
+    assert!(
+        Value::Rational {
+            quotient: 0,
+            remainder: 1,
+            divisor: -2
+        } < third
+    );
+}
+
+#[test]
+fn test_value_eq_hash() {
+    use std::collections::HashSet;
+
+    let half = fraction(1, 2);
+    let unreduced = Value::Rational {
+        quotient: 0,
+        remainder: 2,
+        divisor: 4,
+    };
+    let negated_divisor = Value::Rational {
+        quotient: 1,
+        remainder: 1,
+        divisor: -2,
+    };
+    let whole = Value::Rational {
+        quotient: 1,
+        remainder: 3,
+        divisor: 3,
+    };
+
+    assert_eq!(unreduced, half);
+    assert_eq!(negated_divisor, half);
+    assert_eq!(whole, Value::from(2));
+    assert_eq!(whole, 2);
+    assert_ne!(unreduced, fraction(1, 3));
+    assert_eq!(Value::Undefined, Value::Undefined);
+    assert_ne!(Value::Infinity, Value::NegInfinity);
+    assert_ne!(Value::Infinity, Value::from(i64::MAX));
+
+    let set: HashSet<Value> = [half, unreduced, negated_divisor, whole, Value::from(2)]
+        .iter()
+        .copied()
+        .collect();
+    assert_eq!(set.len(), 2);
+}
+
+#[test]
+fn test_value_non_finite() {
+    let (inf, neg_inf, undefined) = (Value::Infinity, Value::NegInfinity, Value::Undefined);
+    let half = Value::from(0.5);
+
+    assert_eq!(inf + Value::from(1), inf);
+    assert_eq!(Value::from(1) - inf, neg_inf);
+    assert_eq!(inf - inf, undefined);
+    assert_eq!(inf + neg_inf, undefined);
+    assert_eq!(undefined + half, undefined);
+    assert_eq!(inf * -half, neg_inf);
+    assert_eq!(neg_inf * neg_inf, inf);
+    assert_eq!(inf * Value::from(0), undefined);
+    assert_eq!(half / inf, Value::from(0));
+    assert_eq!(inf / inf, undefined);
+    assert_eq!(-inf, neg_inf);
+    assert_eq!(neg_inf.abs(), inf);
+    assert_eq!(neg_inf.checked_pow(3), Some(neg_inf));
+    assert_eq!(inf.checked_powi(-2), Some(Value::from(0)));
+
+    assert_eq!(Value::from(3).divide_by_zero(), inf);
+    assert_eq!(Value::from(-0.5).divide_by_zero(), neg_inf);
+    assert_eq!(Value::from(0).divide_by_zero(), undefined);
+
+    assert!(neg_inf < Value::from(i64::MIN) && Value::from(i64::MAX) < inf);
+    assert_eq!(undefined.partial_cmp(&Value::from(0)), None);
+    assert_eq!(inf.to_f64(), f64::INFINITY);
+    assert!(undefined.to_f64().is_nan());
+    assert_eq!(neg_inf.to_string(), "-inf");
+    // unlike NaN, equal to itself
+    assert_eq!(undefined, undefined);
+    assert_eq!(undefined.partial_cmp(&undefined), None);
+}
+
+#[test]
+fn test_value_non_finite_methods() {
+    let (inf, neg_inf, undefined) = (Value::Infinity, Value::NegInfinity, Value::Undefined);
+
+    assert_eq!(inf.floor(), inf);
+    assert_eq!(neg_inf.ceil(), neg_inf);
+    assert_eq!(undefined.floor(), undefined);
+    assert_eq!(inf.round_half_even(), i64::MAX);
+    assert_eq!(neg_inf.round_half_even(), i64::MIN);
+    assert_eq!(undefined.round_half_even(), 0);
+
+    assert_eq!(inf.format_signed(), "+inf");
+    assert_eq!(neg_inf.format_signed(), "-inf");
+    assert_eq!(undefined.format_signed(), "undefined");
+    assert_eq!(inf.to_decimal(3), "inf");
+    assert_eq!(neg_inf.to_latex(), "-\\infty");
+    assert_eq!(undefined.to_latex(), "\\text{undefined}");
+
+    assert_eq!(neg_inf.as_fraction().to_string(), "-1/0");
+    assert_eq!(inf.to_mixed(), (0, 1, 0));
+    assert_eq!(undefined.to_mixed(), (0, 0, 0));
+    assert_eq!(Value::mediant(Value::from(1), inf), Value::from(2));
+    assert_eq!(Value::mediant(inf, inf), inf);
+    assert_eq!(Value::mediant(undefined, Value::from(1)), undefined);
+    assert_eq!(common_denominator(&[Value::from(0.5), inf]), 2);
+    assert_eq!(inf.limit_denominator(10), inf);
+
+    assert_eq!(inf.pow(-1), Value::from(0));
+    assert_eq!(neg_inf.pow(3), neg_inf);
+    assert_eq!(Value::from(3).div_floor(inf), Value::from(0));
+    assert_eq!(Value::from(3).rem(neg_inf, Modulo::Floored), Value::from(3));
+    assert_eq!(inf.rem(Value::from(2), Modulo::Truncated), undefined);
+}
+
+#[test]
+fn test_value_conversions() {
+    assert_eq!(i64::try_from(Value::from(-12)), Ok(-12));
+    assert_eq!(i64::try_from(Value::from(6) / Value::from(3)), Ok(2));
+    assert_eq!(
+        i64::try_from(Value::Rational {
+            quotient: 1,
+            remainder: 2,
+            divisor: 2
+        }),
+        Ok(2)
+    );
+    assert_eq!(
+        i64::try_from(Value::from(7) / Value::from(2)),
+        Err(EvalError::NotAnInteger)
+    );
+    assert_eq!(i64::try_from(Value::Infinity), Err(EvalError::NotAnInteger));
+
+    use std::convert::TryInto;
+    let n: Result<i64, _> = crate::tree::Tree::new("2 * 21").evaluate().try_into();
+    assert_eq!(n, Ok(42));
+
+    assert_eq!(f64::from(Value::from(-3) / Value::from(4)), -0.75);
+    assert_eq!(f64::from(Value::from(9)), 9.0);
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn test_value_random_in() {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    let (min, max) = (
+        Value::from(-3) / Value::from(2),
+        Value::from(7) / Value::from(3),
+    );
+    let values = |seed: u64| {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..100)
+            .map(|_| Value::random_in(min, max, &mut rng))
+            .collect::<Vec<_>>()
+    };
+
+    let first = values(42);
+    assert_eq!(first, values(42));
+    assert_ne!(first, values(7));
+    assert!(first.iter().all(|v| min <= *v && *v <= max));
+    assert!(first
+        .iter()
+        .all(|v| v.as_fraction().denominator <= RANDOM_MAX_DENOMINATOR));
+
+    let mut rng = StdRng::seed_from_u64(0);
+    assert_eq!(Value::random_in(max, max, &mut rng), max);
+}
+
+#[test]
+fn test_value_rational64() {
+    let three_quarters = Rational64::new(6, 8);
+    let value = Value::from(three_quarters);
+
+    assert_eq!(value, Value::from(0.75));
+    assert_eq!(value, three_quarters);
+    assert_eq!(Value::from(Rational64::new(-3, -1)), Value::Integer(3));
+    assert_eq!(
+        Rational64::try_from(value * Value::from(2)),
+        Ok(Rational64::new(3, 2))
+    );
+    assert_eq!(
+        Rational64::try_from(value - Value::from(1)),
+        Ok(Rational64::new(-1, 4))
+    );
+    assert_eq!(
+        Rational64::try_from(Value::Infinity),
+        Err(EvalError::OutOfDomain)
+    );
+    assert_eq!(Value::from(1) / Value::from(3), Rational64::new(2, 6));
+    assert!(Value::from(1) / Value::from(3) != Rational64::new(1, 2));
+    assert!(Value::Infinity != Rational64::new(1, 1));
+}
+
+#[test]
+fn test_value_pow_mod() {
+    let pow_mod =
+        |base: i64, exp: i64, modulus: i64| Value::pow_mod(base.into(), exp.into(), modulus.into());
+
+    assert_eq!(pow_mod(2, 10, 1000), Ok(Value::from(24)));
+    assert_eq!(pow_mod(3, 0, 7), Ok(Value::from(1)));
+    assert_eq!(pow_mod(3, 0, 1), Ok(Value::from(0)));
+    assert_eq!(pow_mod(-2, 3, 5), Ok(Value::from(2)));
+    assert_eq!(pow_mod(7, 1 << 40, 13), Ok(Value::from(9)));
+    assert_eq!(
+        pow_mod(i64::MAX, i64::MAX, i64::MAX - 1),
+        Ok(Value::from(1))
+    );
+
+    assert_eq!(pow_mod(2, -1, 7), Err(EvalError::OutOfDomain));
+    assert_eq!(pow_mod(2, 3, 0), Err(EvalError::OutOfDomain));
+    assert_eq!(pow_mod(2, 3, -5), Err(EvalError::OutOfDomain));
+    assert_eq!(
+        Value::pow_mod(Value::from(0.5), 2.into(), 7.into()),
+        Err(EvalError::NotAnInteger)
+    );
+}
+
+#[test]
+fn test_value_new_rational() {
+    let as_fraction = |numerator: i64, denominator: i64| {
+        Value::new_rational(numerator, denominator).map(|v| v.as_fraction())
+    };
+
+    assert_eq!(
+        Value::new_rational(6, 4),
+        Ok(Value::Rational {
+            quotient: 1,
+            remainder: 1,
+            divisor: 2
+        })
+    );
+    assert_eq!(Value::new_rational(8, 4), Ok(Value::Integer(2)));
+    assert_eq!(
+        as_fraction(3, -9).map(|f| f.to_string()),
+        Ok("-1/3".to_string())
+    );
+    assert_eq!(
+        as_fraction(-3, -9).map(|f| f.to_string()),
+        Ok("1/3".to_string())
+    );
+    assert_eq!(Value::new_rational(0, -5), Ok(Value::Integer(0)));
+    assert_eq!(Value::new_rational(1, 0), Err(EvalError::DivideByZero));
+    assert_eq!(Value::new_rational(i64::MIN, -1), Err(EvalError::Overflow));
+}
+
+#[test]
+fn test_value_median() {
+    assert_eq!(
+        median(&[fraction(1, 2), fraction(1, 3), fraction(1, 4)]),
+        Some(fraction(1, 3))
+    );
+    assert_eq!(
+        median(&[
+            fraction(1, 2),
+            fraction(1, 3),
+            fraction(1, 4),
+            Value::from(1)
+        ]),
+        Some(fraction(5, 12))
+    );
+    assert_eq!(median(&[Value::from(-7)]), Some(Value::from(-7)));
+    assert_eq!(median(&[]), None);
+
+    let mut values = vec![
+        Value::from(2),
+        Value::Undefined,
+        fraction(-1, 2),
+        Value::NegInfinity,
+        fraction(1, 3),
+    ];
+    sort_values(&mut values);
+    assert_eq!(
+        values,
+        vec![
+            Value::NegInfinity,
+            fraction(-1, 2),
+            fraction(1, 3),
+            Value::from(2),
+            Value::Undefined
+        ]
+    );
+}
+
+#[test]
+fn test_value_to_engineering_string() {
+    assert_eq!(Value::from(1500).to_engineering_string(), "1.5k");
+    assert_eq!(fraction(1, 1000).to_engineering_string(), "1m");
+    assert_eq!(Value::from(-2_200_000).to_engineering_string(), "-2.2M");
+    assert_eq!(fraction(47, 1_000_000).to_engineering_string(), "47µ");
+    assert_eq!(fraction(1, 3).to_engineering_string(), "333.333m");
+    assert_eq!(Value::from(42).to_engineering_string(), "42");
+    assert_eq!(fraction(9_999_999, 10_000).to_engineering_string(), "1k");
+    assert_eq!(Value::from(0).to_engineering_string(), "0");
+}
+
+#[test]
+fn test_value_try_from_str() {
+    let as_fraction = |s: &str| Value::try_from(s).map(|v| v.as_fraction().to_string());
+
+    // neither side is exact as an f64
+    assert_eq!(
+        as_fraction("9007199254740993/9"),
+        Ok("3002399751580331/3".to_string())
+    );
+    assert_eq!(
+        as_fraction(" -9000000000000000006/4 "),
+        Ok("-4500000000000000003/2".to_string())
+    );
+    assert_eq!(as_fraction("1000000000/3"), Ok("1000000000/3".to_string()));
+    assert_eq!(as_fraction("6/-4"), Ok("-3/2".to_string()));
+    assert_eq!(Value::try_from("1/0"), Err(EvalError::DivideByZero));
+
+    // anything else is an expression
+    assert_eq!(Value::try_from("1/2 + 1/2"), Ok(Value::from(1)));
+    assert_eq!(Value::try_from("7//2"), Ok(Value::from(3)));
+    assert_eq!(
+        Value::try_from("9007199254740993 / 9"),
+        Value::try_from("9007199254740993/9")
+    );
+}
+
+#[test]
+fn test_value_zero_one() {
+    use num::{One, Zero};
+
+    for x in [
+        Value::from(3),
+        Value::from(-0.25),
+        Value::from(7) / Value::from(3),
+    ] {
+        assert_eq!(Value::zero() + x, x);
+        assert_eq!(Value::one() * x, x);
+        assert_eq!(x * Value::zero(), Value::zero());
+    }
+    assert!(Value::zero().is_zero());
+    assert!(!Value::from(0.5).is_zero());
+    assert!(Value::one().is_one());
+    assert_eq!(<Value as Zero>::zero(), Value::Integer(0));
+
+    let fractions = (1..=4).map(|d| Value::from(1) / Value::from(d));
+    assert_eq!(
+        fractions.clone().sum::<Value>(),
+        Value::from(25) / Value::from(12)
+    );
+    assert_eq!(
+        fractions.product::<Value>(),
+        Value::from(1) / Value::from(24)
+    );
+    assert_eq!(std::iter::empty::<Value>().sum::<Value>(), Value::zero());
+}
+
+#[test]
+fn test_value_to_exact_and_decimal() {
+    assert_eq!(fraction(1, 2).to_exact_and_decimal(), "1/2 (= 0.5)");
+    assert_eq!(fraction(-7, 4).to_exact_and_decimal(), "-7/4 (= -1.75)");
+    assert_eq!(
+        fraction(1, 3).to_exact_and_decimal(),
+        "1/3 (= 0.3333333333)"
+    );
+    assert_eq!(Value::from(12).to_exact_and_decimal(), "12");
+    assert_eq!(fraction(10, 5).to_exact_and_decimal(), "2");
+    assert_eq!(
+        fraction(1, 1_000_000_000_000).to_exact_and_decimal(),
+        "1/1000000000000 (= 0)"
+    );
+}
+
+#[test]
+fn test_value_mul_add() {
+    assert_eq!(
+        Value::from(2).mul_add(Value::from(3), Value::from(4)),
+        Value::from(10)
+    );
+    assert_eq!(
+        fraction(1, 2).mul_add(fraction(2, 3), fraction(1, 6)),
+        fraction(1, 2)
+    );
+    assert_eq!(
+        fraction(-3, 4).mul_add(Value::from(2), Value::from(1)),
+        fraction(-1, 2)
+    );
+
+    // the product overflows on its own, but the sum fits
+    let max = Value::from(i64::MAX);
+    assert_eq!(max.checked_mul(Value::from(2)), None);
+    assert_eq!(max.mul_add(Value::from(2), -max), max);
+    assert_eq!(
+        max.mul_add(fraction(1, 3), fraction(2, 3)),
+        Value::from(3_074_457_345_618_258_603)
+    );
+    // and with denominators whose product doesn't fit
+    let near_one = fraction(i64::MAX - 1, i64::MAX);
+    assert_eq!(
+        near_one.mul_add(fraction(i64::MAX, i64::MAX - 1), fraction(-1, 2)),
+        fraction(1, 2)
+    );
+    assert_eq!(
+        fraction(1, i64::MAX).mul_add(fraction(1, 2), fraction(1, 2)),
+        fraction(1 << 62, i64::MAX)
+    );
+
+    // Horner's method for 2x^2 - 3x + 1 at x = 1/2
+    let x = fraction(1, 2);
+    let horner = [2, -3, 1]
+        .iter()
+        .fold(Value::from(0), |acc, &c| acc.mul_add(x, Value::from(c)));
+    assert_eq!(horner, Value::from(0));
+}
+
+#[test]
+fn test_value_limit_denominator() {
+    assert_eq!(
+        fraction(-314159, 100000).limit_denominator(100),
+        fraction(-311, 99)
+    );
+    assert_eq!(fraction(3, 7).limit_denominator(5), fraction(2, 5));
+    assert_eq!(fraction(1, 3).limit_denominator(2), fraction(1, 2));
+    assert_eq!(fraction(1, 3).limit_denominator(3), fraction(1, 3));
+    assert_eq!(fraction(2, 3).limit_denominator(1), Value::from(1));
+    assert_eq!(Value::from(5).limit_denominator(1), Value::from(5));
+    assert_eq!(
+        (Value::from(1) / Value::from(i64::MAX)).limit_denominator(1000),
+        Value::from(0)
+    );
+}
+
+#[test]
+fn test_value_parse_literal() {
+    assert_eq!(Value::parse_literal("42"), Ok(Value::from(42)));
+    assert_eq!(Value::parse_literal(" -7 "), Ok(Value::from(-7)));
+    assert_eq!(Value::parse_literal("0.1"), Ok(fraction(1, 10)));
+    assert_eq!(Value::parse_literal("-2.50"), Ok(fraction(-5, 2)));
+    assert_eq!(Value::parse_literal(".25"), Ok(fraction(1, 4)));
+    assert_eq!(Value::parse_literal("6/8"), Ok(fraction(3, 4)));
+    assert_eq!(Value::parse_literal("3 / -9"), Ok(fraction(-1, 3)));
+    assert_eq!(Value::parse_literal("50%"), Ok(fraction(1, 2)));
+    assert_eq!(Value::parse_literal("12.5 %"), Ok(fraction(1, 8)));
+    assert_eq!(Value::parse_literal("150%"), Ok(fraction(3, 2)));
+
+    let invalid = |s: &str| Err(LexError::InvalidNumber(s.to_string()));
+    assert_eq!(Value::parse_literal("2+2"), invalid("2+2"));
+    assert_eq!(Value::parse_literal("1/0"), invalid("1/0"));
+    assert_eq!(Value::parse_literal("1.2.3"), invalid("1.2.3"));
+    assert_eq!(Value::parse_literal("1/2/3"), invalid("1/2/3"));
+    assert_eq!(Value::parse_literal("%"), invalid("%"));
+}
+
+#[test]
+fn test_values_to_f64() {
+    let values = [
+        Value::from(3),
+        fraction(1, 4),
+        fraction(-7, 2),
+        Value::from(0),
+        Value::Infinity,
+    ];
+    assert_eq!(
+        values_to_f64(&values),
+        vec![3.0, 0.25, -3.5, 0.0, f64::INFINITY]
+    );
+    assert!(values_to_f64(&[Value::Undefined])[0].is_nan());
+    assert!(values_to_f64(&[]).is_empty());
+}
+
+#[test]
+fn test_value_harmonic() {
+    assert_eq!(Value::harmonic(0), Value::from(0));
+    assert_eq!(Value::harmonic(1), Value::from(1));
+    assert_eq!(Value::harmonic(2), Value::from(1.5));
+    assert_eq!(Value::harmonic(4), fraction(25, 12));
+    assert_eq!(Value::harmonic(10), fraction(7381, 2520));
+    assert!(Value::harmonic(46).to_f64() > 4.4);
+}
+
+#[test]
+fn test_common_denominator() {
+    assert_eq!(
+        common_denominator(&[fraction(1, 2), fraction(1, 3), fraction(1, 4)]),
+        12
+    );
+    // reduced first, 2/4 only needs a 2
+    assert_eq!(common_denominator(&[fraction(2, 4), fraction(-5, 6)]), 6);
+    assert_eq!(common_denominator(&[Value::from(3), fraction(7, 5)]), 5);
+    assert_eq!(common_denominator(&[Value::from(3)]), 1);
+    assert_eq!(common_denominator(&[]), 1);
+}
+
+#[test]
+fn test_value_integer_add_overflow() {
+    let max = Value::Integer(i64::MAX);
+    let min = Value::Integer(i64::MIN);
+
+    assert_eq!(max.try_add(Value::Integer(1)), Err(EvalError::Overflow));
+    assert_eq!(min.try_add(Value::Integer(-1)), Err(EvalError::Overflow));
+    assert_eq!(min.try_sub(Value::Integer(1)), Err(EvalError::Overflow));
+    // right at the edge still fits
+    assert_eq!(
+        max.try_add(Value::Integer(-1)),
+        Ok(Value::Integer(i64::MAX - 1))
+    );
+    assert_eq!(min.try_add(max), Ok(Value::Integer(-1)));
+}
+
+#[test]
+#[should_panic(expected = "Overflow")]
+fn test_value_integer_add_overflow_panics() {
+    let _ = Value::Integer(i64::MAX) + Value::Integer(1);
+}
+
+#[test]
+fn test_value_is_dyadic() {
+    assert!(fraction(3, 4).is_dyadic());
+    assert!(fraction(-5, 1024).is_dyadic());
+    assert!(fraction(6, 12).is_dyadic());
+    assert!(Value::from(7).is_dyadic());
+    assert!(!fraction(1, 3).is_dyadic());
+    assert!(!fraction(1, 10).is_dyadic());
+    assert!(!Value::Infinity.is_dyadic());
+    assert!(!Value::Undefined.is_dyadic());
+}
+
+#[test]
+fn test_value_to_improper_string() {
+    let rational = |quotient: i64, remainder: i64, divisor: i64| Value::Rational {
+        quotient,
+        remainder,
+        divisor,
+    };
+
+    assert_eq!(rational(3, 1, 2).to_improper_string(), "7/2");
+    // unreduced and out of range remainders are normalized
+    assert_eq!(rational(3, 2, 4).to_improper_string(), "7/2");
+    assert_eq!(rational(2, 3, 2).to_improper_string(), "7/2");
+    assert_eq!(rational(0, 7, 2).to_improper_string(), "7/2");
+    // the sign ends up on the numerator
+    assert_eq!(rational(-3, -1, 2).to_improper_string(), "-7/2");
+    assert_eq!(rational(-4, 1, 2).to_improper_string(), "-7/2");
+    assert_eq!(rational(3, 1, -2).to_improper_string(), "5/2");
+    // whole values are bare integers
+    assert_eq!(rational(2, 0, 5).to_improper_string(), "2");
+    assert_eq!(rational(1, 3, 3).to_improper_string(), "2");
+    assert_eq!(Value::from(-12).to_improper_string(), "-12");
+    assert_eq!(Value::Infinity.to_improper_string(), "inf");
+}
+
+#[test]
+fn test_value_neg_min() {
+    let min = Value::Integer(i64::MIN);
+
+    assert_eq!(min.checked_neg(), None);
+    assert_eq!(min.try_mul(Value::from(-1)), Err(EvalError::Overflow));
+    assert_eq!(-Value::Integer(i64::MIN + 1), Value::Integer(i64::MAX));
+    // a remainder of `i64::MIN` can still be negated when the value fits
+    let unreduced = Value::Rational {
+        quotient: 1,
+        remainder: i64::MIN,
+        divisor: 2,
+    };
+    assert_eq!(-unreduced, Value::from((1i64 << 62) - 1));
+    assert_eq!(-fraction(7, 2), fraction(-7, 2));
+}
+
+#[test]
+#[should_panic(expected = "Overflow")]
+fn test_value_neg_min_panics() {
+    let _ = -Value::Integer(i64::MIN);
+}
+
+#[test]
+fn test_value_to_unicode_fraction() {
+    assert_eq!(fraction(1, 2).to_unicode_fraction(), "½");
+    assert_eq!(fraction(7, 2).to_unicode_fraction(), "3½");
+    assert_eq!(fraction(-7, 4).to_unicode_fraction(), "-1¾");
+    assert_eq!(fraction(-2, 3).to_unicode_fraction(), "-⅔");
+    assert_eq!(fraction(6, 16).to_unicode_fraction(), "⅜");
+    assert_eq!(fraction(1, 7).to_unicode_fraction(), "1/7");
+    assert_eq!(fraction(22, 7).to_unicode_fraction(), "22/7");
+    assert_eq!(Value::from(5).to_unicode_fraction(), "5");
+    assert_eq!(Value::NegInfinity.to_unicode_fraction(), "-inf");
 }
@@ -0,0 +1,74 @@
+//! The configuration is global to the process, so it's tested on its own rather than alongside the
+//! unit tests that rely on the default `Display`
+
+use rational_calculator::{
+    config::{config, set_config, Config, DisplayStyle, OverflowMode},
+    tree::{EvalMode, Tree},
+    Value,
+};
+
+fn fraction(numerator: i64, denominator: i64) -> Value {
+    Value::new_rational(numerator, denominator).unwrap()
+}
+
+#[test]
+fn test_config() {
+    let seven_halves = Value::from(7) / Value::from(2);
+    let third = Value::from(1) / Value::from(3);
+
+    assert_eq!(config(), Config::default());
+    assert_eq!(seven_halves.to_string(), "3 (1 / 2)");
+
+    set_config(Config {
+        display: DisplayStyle::Decimal,
+        precision: 3,
+        ..Config::default()
+    });
+    assert_eq!(seven_halves.to_string(), "3.500");
+    assert_eq!((-third).to_string(), "-0.333");
+
+    set_config(Config {
+        display: DisplayStyle::Decimal,
+        precision: 1,
+        ..config()
+    });
+    assert_eq!(third.to_string(), "0.3");
+
+    set_config(Config {
+        display: DisplayStyle::Fraction,
+        ..config()
+    });
+    assert_eq!(seven_halves.to_string(), "7/2");
+
+    set_config(Config {
+        overflow: OverflowMode::Saturate,
+        ..Config::default()
+    });
+    assert_eq!(
+        Value::from(i64::MAX) + Value::from(1),
+        Value::from(i64::MAX)
+    );
+    assert_eq!(
+        Value::from(i64::MIN) * Value::from(2),
+        Value::from(i64::MIN)
+    );
+    assert_eq!(
+        Value::from(i64::MAX) * Value::from(-3),
+        Value::from(i64::MIN)
+    );
+    assert_eq!(-Value::from(i64::MIN), Value::from(i64::MAX));
+
+    set_config(Config {
+        max_denominator: Some(1000),
+        ..Config::default()
+    });
+    let sum = "1/2 + 1/3 + 1/5 + 1/7 + 1/11";
+    assert_eq!(Tree::new(sum).evaluate(), fraction(389, 307));
+    assert_eq!(EvalMode::from_config().max_denominator, Some(1000));
+    // the arithmetic on values stays exact
+    assert_eq!(fraction(247, 210) + fraction(1, 11), fraction(2927, 2310));
+
+    set_config(Config::default());
+    assert_eq!(seven_halves.to_string(), "3 (1 / 2)");
+    assert_eq!(Tree::new(sum).evaluate(), fraction(2927, 2310));
+}